@@ -0,0 +1,189 @@
+//! the parts of the AWS apis cronitor calls
+//!
+//! each trait is implemented for any implementation of the matching rusoto
+//! service trait, including its default client, and is narrow enough to
+//! implement in memory, e.g. to test against canned responses
+
+use async_trait::async_trait;
+use rusoto_cloudwatch::{
+    CloudWatch, GetMetricStatisticsError, GetMetricStatisticsInput, GetMetricStatisticsOutput,
+    ListMetricsError, ListMetricsInput, ListMetricsOutput, PutMetricDataError, PutMetricDataInput,
+};
+use rusoto_core::RusotoError;
+use rusoto_ecs::{
+    DescribeClustersError, DescribeClustersRequest, DescribeClustersResponse,
+    DescribeTaskDefinitionError, DescribeTaskDefinitionRequest, DescribeTaskDefinitionResponse,
+    DescribeTasksError, DescribeTasksRequest, DescribeTasksResponse, Ecs, ListClustersError,
+    ListClustersRequest, ListClustersResponse, ListTasksError, ListTasksRequest, ListTasksResponse,
+};
+use rusoto_events::{
+    DescribeRuleError, DescribeRuleRequest, DescribeRuleResponse, EventBridge, ListRulesError,
+    ListRulesRequest, ListRulesResponse, ListTagsForResourceError, ListTagsForResourceRequest,
+    ListTagsForResourceResponse, ListTargetsByRuleError, ListTargetsByRuleRequest,
+    ListTargetsByRuleResponse,
+};
+
+/// the event rule calls cronitor makes, see `EventBridge`
+#[async_trait]
+pub trait EventsApi: Sync {
+    async fn list_rules(
+        &self,
+        input: ListRulesRequest,
+    ) -> Result<ListRulesResponse, RusotoError<ListRulesError>>;
+
+    async fn describe_rule(
+        &self,
+        input: DescribeRuleRequest,
+    ) -> Result<DescribeRuleResponse, RusotoError<DescribeRuleError>>;
+
+    async fn list_targets_by_rule(
+        &self,
+        input: ListTargetsByRuleRequest,
+    ) -> Result<ListTargetsByRuleResponse, RusotoError<ListTargetsByRuleError>>;
+
+    async fn list_tags_for_resource(
+        &self,
+        input: ListTagsForResourceRequest,
+    ) -> Result<ListTagsForResourceResponse, RusotoError<ListTagsForResourceError>>;
+}
+
+#[async_trait]
+impl<T: EventBridge + Sync> EventsApi for T {
+    async fn list_rules(
+        &self,
+        input: ListRulesRequest,
+    ) -> Result<ListRulesResponse, RusotoError<ListRulesError>> {
+        EventBridge::list_rules(self, input).await
+    }
+
+    async fn describe_rule(
+        &self,
+        input: DescribeRuleRequest,
+    ) -> Result<DescribeRuleResponse, RusotoError<DescribeRuleError>> {
+        EventBridge::describe_rule(self, input).await
+    }
+
+    async fn list_targets_by_rule(
+        &self,
+        input: ListTargetsByRuleRequest,
+    ) -> Result<ListTargetsByRuleResponse, RusotoError<ListTargetsByRuleError>> {
+        EventBridge::list_targets_by_rule(self, input).await
+    }
+
+    async fn list_tags_for_resource(
+        &self,
+        input: ListTagsForResourceRequest,
+    ) -> Result<ListTagsForResourceResponse, RusotoError<ListTagsForResourceError>> {
+        EventBridge::list_tags_for_resource(self, input).await
+    }
+}
+
+/// the cloudwatch calls cronitor makes, see `CloudWatch`
+#[async_trait]
+pub trait MetricsApi: Sync {
+    async fn get_metric_statistics(
+        &self,
+        input: GetMetricStatisticsInput,
+    ) -> Result<GetMetricStatisticsOutput, RusotoError<GetMetricStatisticsError>>;
+
+    async fn list_metrics(
+        &self,
+        input: ListMetricsInput,
+    ) -> Result<ListMetricsOutput, RusotoError<ListMetricsError>>;
+
+    async fn put_metric_data(
+        &self,
+        input: PutMetricDataInput,
+    ) -> Result<(), RusotoError<PutMetricDataError>>;
+}
+
+#[async_trait]
+impl<T: CloudWatch + Sync> MetricsApi for T {
+    async fn get_metric_statistics(
+        &self,
+        input: GetMetricStatisticsInput,
+    ) -> Result<GetMetricStatisticsOutput, RusotoError<GetMetricStatisticsError>> {
+        CloudWatch::get_metric_statistics(self, input).await
+    }
+
+    async fn list_metrics(
+        &self,
+        input: ListMetricsInput,
+    ) -> Result<ListMetricsOutput, RusotoError<ListMetricsError>> {
+        CloudWatch::list_metrics(self, input).await
+    }
+
+    async fn put_metric_data(
+        &self,
+        input: PutMetricDataInput,
+    ) -> Result<(), RusotoError<PutMetricDataError>> {
+        CloudWatch::put_metric_data(self, input).await
+    }
+}
+
+/// the ecs calls cronitor makes, see `Ecs`
+#[async_trait]
+pub trait EcsApi: Sync {
+    async fn list_tasks(
+        &self,
+        input: ListTasksRequest,
+    ) -> Result<ListTasksResponse, RusotoError<ListTasksError>>;
+
+    async fn describe_tasks(
+        &self,
+        input: DescribeTasksRequest,
+    ) -> Result<DescribeTasksResponse, RusotoError<DescribeTasksError>>;
+
+    async fn describe_task_definition(
+        &self,
+        input: DescribeTaskDefinitionRequest,
+    ) -> Result<DescribeTaskDefinitionResponse, RusotoError<DescribeTaskDefinitionError>>;
+
+    async fn describe_clusters(
+        &self,
+        input: DescribeClustersRequest,
+    ) -> Result<DescribeClustersResponse, RusotoError<DescribeClustersError>>;
+
+    async fn list_clusters(
+        &self,
+        input: ListClustersRequest,
+    ) -> Result<ListClustersResponse, RusotoError<ListClustersError>>;
+}
+
+#[async_trait]
+impl<T: Ecs + Sync> EcsApi for T {
+    async fn list_tasks(
+        &self,
+        input: ListTasksRequest,
+    ) -> Result<ListTasksResponse, RusotoError<ListTasksError>> {
+        Ecs::list_tasks(self, input).await
+    }
+
+    async fn describe_tasks(
+        &self,
+        input: DescribeTasksRequest,
+    ) -> Result<DescribeTasksResponse, RusotoError<DescribeTasksError>> {
+        Ecs::describe_tasks(self, input).await
+    }
+
+    async fn describe_task_definition(
+        &self,
+        input: DescribeTaskDefinitionRequest,
+    ) -> Result<DescribeTaskDefinitionResponse, RusotoError<DescribeTaskDefinitionError>> {
+        Ecs::describe_task_definition(self, input).await
+    }
+
+    async fn describe_clusters(
+        &self,
+        input: DescribeClustersRequest,
+    ) -> Result<DescribeClustersResponse, RusotoError<DescribeClustersError>> {
+        Ecs::describe_clusters(self, input).await
+    }
+
+    async fn list_clusters(
+        &self,
+        input: ListClustersRequest,
+    ) -> Result<ListClustersResponse, RusotoError<ListClustersError>> {
+        Ecs::list_clusters(self, input).await
+    }
+}
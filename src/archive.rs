@@ -0,0 +1,86 @@
+//! rendered reports archived to s3
+
+use crate::{retry, CronitorError, Retries};
+use chrono::{DateTime, Utc};
+use log::info;
+use rusoto_s3::{PutObjectRequest, S3};
+use std::{fmt, str::FromStr};
+
+/// an s3 location reports are written under, e.g. s3://bucket/prefix
+#[derive(Clone, Debug)]
+pub struct S3Uri {
+    pub bucket: String,
+    /// key prefix, without leading or trailing slashes
+    pub prefix: String,
+}
+
+impl S3Uri {
+    /// the key of a report gathered at `at`, named by its timestamp
+    pub fn key(
+        &self,
+        at: DateTime<Utc>,
+        extension: &str,
+    ) -> String {
+        let name = format!("{}.{}", at.format("%Y-%m-%dT%H-%M-%SZ"), extension);
+        if self.prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", self.prefix, name)
+        }
+    }
+}
+
+impl fmt::Display for S3Uri {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        write!(f, "s3://{}/{}", self.bucket, self.prefix)
+    }
+}
+
+impl FromStr for S3Uri {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let path = value
+            .strip_prefix("s3://")
+            .ok_or_else(|| format!("invalid s3 uri '{}': expected s3://bucket/prefix", value))?;
+        let (bucket, prefix) = match path.find('/') {
+            Some(split) => (&path[..split], &path[split + 1..]),
+            _ => (path, ""),
+        };
+        if bucket.is_empty() {
+            return Err(format!("invalid s3 uri '{}': missing bucket", value));
+        }
+        Ok(S3Uri {
+            bucket: bucket.into(),
+            prefix: prefix.trim_matches('/').into(),
+        })
+    }
+}
+
+/// upload a rendered report under `uri`, keyed by when it was gathered,
+/// returning the key written
+pub async fn upload<S: S3>(
+    s3: &S,
+    uri: &S3Uri,
+    contents: &str,
+    content_type: &str,
+    extension: &str,
+    at: DateTime<Utc>,
+    retries: &Retries,
+) -> Result<String, CronitorError> {
+    let key = uri.key(at, extension);
+    retry(retries, || {
+        s3.put_object(PutObjectRequest {
+            bucket: uri.bucket.clone(),
+            key: key.clone(),
+            body: Some(contents.as_bytes().to_vec().into()),
+            content_type: Some(content_type.into()),
+            ..PutObjectRequest::default()
+        })
+    })
+    .await?;
+    info!("uploaded report to s3://{}/{}", uri.bucket, key);
+    Ok(key)
+}
@@ -0,0 +1,87 @@
+//! a disk cache of query results, so repeated runs within a ttl don't go
+//! back to AWS
+
+use log::{debug, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+/// a directory of json entries, one per distinct query
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+    /// ignore existing entries, replacing them with fresh results
+    refresh: bool,
+}
+
+impl Cache {
+    pub fn new(
+        dir: PathBuf,
+        ttl: Duration,
+        refresh: bool,
+    ) -> Self {
+        Cache { dir, ttl, refresh }
+    }
+
+    /// the file an entry for `key` is stored in
+    fn path(
+        &self,
+        key: &str,
+    ) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.dir.join(format!("{:016x}.json", hasher.finish()))
+    }
+
+    /// the entry stored for `key`, if there's one younger than the ttl
+    pub fn get<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Option<T> {
+        if self.refresh {
+            return None;
+        }
+        let path = self.path(key);
+        let age = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        if age > self.ttl {
+            return None;
+        }
+        match fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|err| err.to_string()))
+        {
+            Ok(value) => {
+                debug!("serving {} from cache {}", key, path.display());
+                Some(value)
+            }
+            Err(err) => {
+                warn!("ignoring unreadable cache {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    /// store an entry for `key`, warning rather than failing when we can't
+    pub fn put<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+    ) {
+        let path = self.path(key);
+        let written = fs::create_dir_all(&self.dir)
+            .map_err(|err| err.to_string())
+            .and_then(|_| serde_json::to_vec(value).map_err(|err| err.to_string()))
+            .and_then(|bytes| fs::write(&path, bytes).map_err(|err| err.to_string()));
+        if let Err(err) = written {
+            warn!("failed to write cache {}: {}", path.display(), err);
+        }
+    }
+}
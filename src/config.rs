@@ -0,0 +1,76 @@
+//! defaults for command line options read from a toml file
+//!
+//! keys are the long names of global options, e.g.
+//!
+//! ```toml
+//! rule = ["my-cron-"]
+//! cluster = ["default"]
+//! region = "us-west-2"
+//! max-age = "1d"
+//! exact = true
+//! ```
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+use toml::{value::Table, Value};
+
+/// name of the config file looked for by default
+pub const FILE_NAME: &str = "cronitor.toml";
+
+/// the first default config file which exists, either ./cronitor.toml or
+/// ~/.config/cronitor/cronitor.toml
+pub fn default_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(|home| {
+        PathBuf::from(home)
+            .join(".config")
+            .join("cronitor")
+            .join(FILE_NAME)
+    });
+    Some(PathBuf::from(FILE_NAME))
+        .into_iter()
+        .chain(home)
+        .find(|path| path.is_file())
+}
+
+/// read a config file's table of option defaults
+pub fn load(path: &Path) -> Result<Table, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    toml::from_str(&contents).map_err(|err| format!("invalid config {}: {}", path.display(), err))
+}
+
+/// render config defaults as command line flags, skipping options for which
+/// `provided` says a flag was given so command line flags take precedence
+pub fn args(
+    table: &Table,
+    provided: impl Fn(&str) -> bool,
+) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    for (key, value) in table {
+        if provided(key) {
+            continue;
+        }
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                Value::Boolean(true) => args.push(format!("--{}", key)),
+                Value::Boolean(false) => (),
+                Value::String(value) => args.push(format!("--{}={}", key, value)),
+                Value::Integer(value) => args.push(format!("--{}={}", key, value)),
+                Value::Float(value) => args.push(format!("--{}={}", key, value)),
+                _ => {
+                    return Err(format!(
+                        "invalid config value for {}: expected a string, number, boolean, or list of them",
+                        key
+                    ))
+                }
+            }
+        }
+    }
+    Ok(args)
+}
@@ -0,0 +1,144 @@
+//! errors surfaced while gathering rule history
+
+use rusoto_core::RusotoError;
+use std::{error::Error as StdError, time::Duration};
+use thiserror::Error;
+
+/// error codes AWS services use to signal a caller is being rate limited
+pub const THROTTLE_CODES: &[&str] = &[
+    "Throttling",
+    "ThrottlingException",
+    "RequestLimitExceeded",
+    "RateExceeded",
+    "TooManyRequestsException",
+];
+
+/// error codes AWS services use to signal a caller lacks permission
+const ACCESS_DENIED_CODES: &[&str] = &[
+    "AccessDenied",
+    "AccessDeniedException",
+    "UnauthorizedOperation",
+    "UnrecognizedClientException",
+    "ExpiredToken",
+    "ExpiredTokenException",
+];
+
+/// classes of failure we may want to handle differently, e.g. retrying
+/// only throttled requests
+#[derive(Debug, Error)]
+pub enum CronitorError {
+    #[error("request throttled: {0}")]
+    Throttled(String),
+    #[error("access denied: {0}")]
+    AccessDenied(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("failed to resolve credentials: {0}")]
+    Credentials(String),
+    #[error("http request failed: {0}")]
+    Http(String),
+    #[error("invalid response: {0}")]
+    InvalidResponse(String),
+    #[error("{0}")]
+    Service(String),
+    #[error("timed out before completing")]
+    TimedOut,
+    #[error("timed out after {0:?}")]
+    RuleTimedOut(Duration),
+    #[error("interrupted before completing")]
+    Interrupted,
+}
+
+impl CronitorError {
+    /// true when the request may succeed if retried after a delay
+    pub fn throttled(&self) -> bool {
+        matches!(self, CronitorError::Throttled(_))
+    }
+
+    /// true when the operation was abandoned at its deadline, or for taking
+    /// longer than a single rule may
+    pub fn timed_out(&self) -> bool {
+        matches!(
+            self,
+            CronitorError::TimedOut | CronitorError::RuleTimedOut(_)
+        )
+    }
+
+    /// true when the operation was abandoned because the run was
+    /// interrupted
+    pub fn interrupted(&self) -> bool {
+        matches!(self, CronitorError::Interrupted)
+    }
+
+    /// true when the error carries one of `codes`
+    ///
+    /// throttled and access denied errors don't always include their code,
+    /// e.g. a bare 429, so those match any code of their class
+    pub fn has_code(
+        &self,
+        codes: &[String],
+    ) -> bool {
+        let message = self.to_string();
+        if codes.iter().any(|code| message.contains(code.as_str())) {
+            return true;
+        }
+        let class = match self {
+            CronitorError::Throttled(_) => THROTTLE_CODES,
+            CronitorError::AccessDenied(_) => ACCESS_DENIED_CODES,
+            _ => return false,
+        };
+        !class.iter().any(|code| message.contains(code))
+            && codes.iter().any(|code| class.contains(&code.as_str()))
+    }
+
+    /// classify an error by the AWS error code found in its message
+    fn classify(message: String) -> Self {
+        if THROTTLE_CODES.iter().any(|code| message.contains(code)) {
+            CronitorError::Throttled(message)
+        } else if ACCESS_DENIED_CODES
+            .iter()
+            .any(|code| message.contains(code))
+        {
+            CronitorError::AccessDenied(message)
+        } else if message.contains("NotFound") {
+            CronitorError::NotFound(message)
+        } else {
+            CronitorError::Service(message)
+        }
+    }
+}
+
+impl<E: StdError + 'static> From<RusotoError<E>> for CronitorError {
+    fn from(err: RusotoError<E>) -> Self {
+        match err {
+            // typed service errors are named after their error code, e.g.
+            // ClusterNotFoundException, which debug formatting includes
+            RusotoError::Service(err) => {
+                let classified = CronitorError::classify(format!("{:?}", err));
+                let message = err.to_string();
+                match classified {
+                    CronitorError::Throttled(_) => CronitorError::Throttled(message),
+                    CronitorError::AccessDenied(_) => CronitorError::AccessDenied(message),
+                    CronitorError::NotFound(_) => CronitorError::NotFound(message),
+                    _ => CronitorError::Service(message),
+                }
+            }
+            RusotoError::HttpDispatch(err) => CronitorError::Http(err.to_string()),
+            RusotoError::Credentials(err) => CronitorError::Credentials(err.to_string()),
+            RusotoError::Validation(message) => CronitorError::Service(message),
+            RusotoError::ParseError(message) => CronitorError::InvalidResponse(message),
+            // rusoto surfaces throttling and permission errors as unknown
+            // errors so we sniff the error code from the buffered response
+            RusotoError::Unknown(response) => {
+                let message = response.body_as_str().to_string();
+                match response.status.as_u16() {
+                    429 => CronitorError::Throttled(message),
+                    403 => CronitorError::AccessDenied(message),
+                    404 => CronitorError::NotFound(message),
+                    _ => CronitorError::classify(message),
+                }
+            }
+            RusotoError::Blocking => CronitorError::Http("failed to run blocking future".into()),
+        }
+    }
+}
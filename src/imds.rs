@@ -0,0 +1,109 @@
+//! credentials fetched from the ec2 instance metadata service using the
+//! token based IMDSv2 flow
+//!
+//! https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/configuring-instance-metadata-service.html
+
+use async_trait::async_trait;
+use hyper::{body, Body, Client, Method, Request};
+use rusoto_core::credential::{AwsCredentials, CredentialsError, ProvideAwsCredentials};
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// base url of the instance metadata service
+const METADATA_URL: &str = "http://169.254.169.254/latest";
+
+/// path listing the instance's role, which credentials are then fetched for
+const CREDENTIALS_PATH: &str = "/meta-data/iam/security-credentials/";
+
+/// seconds a session token remains valid, the max allowed
+const TOKEN_TTL_SECONDS: u32 = 21_600;
+
+/// fetches instance role credentials, first requesting a session token as
+/// IMDSv2 requires
+#[derive(Clone, Debug)]
+pub struct Imdsv2Provider {
+    /// how long to wait on each request to the metadata service
+    timeout: Duration,
+}
+
+impl Imdsv2Provider {
+    pub fn new(timeout: Duration) -> Self {
+        Imdsv2Provider { timeout }
+    }
+
+    /// send a request to the metadata service, returning its body
+    async fn request(
+        &self,
+        request: Request<Body>,
+    ) -> Result<String, CredentialsError> {
+        let uri = request.uri().to_string();
+        let fetch = async {
+            let response = Client::new()
+                .request(request)
+                .await
+                .map_err(|e| CredentialsError::new(format!("failed to request {}: {}", uri, e)))?;
+            let status = response.status();
+            let body = body::to_bytes(response.into_body())
+                .await
+                .map_err(|e| CredentialsError::new(format!("failed to read {}: {}", uri, e)))?;
+            if !status.is_success() {
+                return Err(CredentialsError::new(format!(
+                    "instance metadata responded to {} with {}",
+                    uri, status
+                )));
+            }
+            Ok(String::from_utf8_lossy(&body).into_owned())
+        };
+        timeout(self.timeout, fetch).await.map_err(|_| {
+            CredentialsError::new(format!(
+                "timed out after {:?} requesting {}",
+                self.timeout, uri
+            ))
+        })?
+    }
+
+    async fn token(&self) -> Result<String, CredentialsError> {
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri(format!("{}/api/token", METADATA_URL))
+            .header("X-aws-ec2-metadata-token-ttl-seconds", TOKEN_TTL_SECONDS)
+            .body(Body::empty())
+            .map_err(|e| CredentialsError::new(e.to_string()))?;
+        self.request(request).await
+    }
+
+    async fn get(
+        &self,
+        token: &str,
+        path: &str,
+    ) -> Result<String, CredentialsError> {
+        let request = Request::builder()
+            .uri(format!("{}{}", METADATA_URL, path))
+            .header("X-aws-ec2-metadata-token", token)
+            .body(Body::empty())
+            .map_err(|e| CredentialsError::new(e.to_string()))?;
+        self.request(request).await
+    }
+}
+
+#[async_trait]
+impl ProvideAwsCredentials for Imdsv2Provider {
+    async fn credentials(&self) -> Result<AwsCredentials, CredentialsError> {
+        let token = self.token().await?;
+        let roles = self.get(&token, CREDENTIALS_PATH).await?;
+        let role = roles
+            .lines()
+            .map(str::trim)
+            .find(|role| !role.is_empty())
+            .ok_or_else(|| CredentialsError::new("no role is attached to this instance"))?;
+        let credentials = self
+            .get(&token, &format!("{}{}", CREDENTIALS_PATH, role))
+            .await?;
+        serde_json::from_str(&credentials).map_err(|e| {
+            CredentialsError::new(format!(
+                "invalid credentials for role {} from instance metadata: {}",
+                role, e
+            ))
+        })
+    }
+}
@@ -0,0 +1,2816 @@
+//! cronitor gathers the recent history of AWS ECS tasks started by
+//! Cloud Watch event rules
+//!
+//! functions are generic over the traits in `api`, implemented for any of
+//! rusoto's `CloudWatch`, `Ecs`, and `EventBridge` service traits, or over
+//! rusoto's `CloudWatchLogs` and `Sns` traits, so any implementation may be
+//! provided in place of the default clients
+
+use async_trait::async_trait;
+use chrono::{prelude::*, Duration, SecondsFormat};
+use chrono_tz::Tz;
+use futures::{
+    future::{try_join, try_join_all},
+    stream::{self, StreamExt, TryStreamExt},
+    Future,
+};
+use hyper::client::HttpConnector;
+use hyper_tls::HttpsConnector;
+use log::{debug, info, warn};
+use rand::{thread_rng, Rng};
+use rusoto_cloudwatch::{Datapoint, Dimension, GetMetricStatisticsInput, ListMetricsInput};
+use rusoto_core::{
+    credential::{
+        AutoRefreshingProvider, AwsCredentials, ChainProvider, ContainerProvider, CredentialsError,
+        ProfileProvider, ProvideAwsCredentials,
+    },
+    request::{DispatchSignedRequest, DispatchSignedRequestFuture, HttpClient},
+    signature::SignedRequest,
+    Region, RusotoError,
+};
+use rusoto_ecs::{
+    DescribeClustersRequest, DescribeTaskDefinitionError, DescribeTaskDefinitionRequest,
+    DescribeTasksRequest, ListClustersRequest, ListTasksRequest, Task,
+};
+use rusoto_events::{
+    DescribeRuleRequest, ListRulesRequest, ListTagsForResourceRequest, ListTargetsByRuleRequest,
+};
+use rusoto_sts::{
+    GetCallerIdentityRequest, Sts, StsAssumeRoleSessionCredentialsProvider, StsClient,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env, fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration as StdDuration,
+};
+use tokio::{
+    sync::Notify,
+    time::{sleep, timeout, timeout_at, Instant},
+};
+
+pub mod api;
+pub mod archive;
+pub mod cache;
+pub mod config;
+pub mod error;
+pub mod imds;
+pub mod logs;
+pub mod notify;
+pub mod relative;
+pub mod schedule;
+
+use api::{EcsApi, EventsApi, MetricsApi};
+pub use error::CronitorError;
+use error::THROTTLE_CODES;
+use imds::Imdsv2Provider;
+use schedule::Schedule;
+
+/// max number of task arns accepted by a single describe_tasks request
+const DESCRIBE_TASKS_LIMIT: usize = 100;
+
+/// max number of datapoints returned by a single get_metric_statistics request
+pub const MAX_DATAPOINTS: i64 = 1440;
+
+/// namespace of the metrics Cloud Watch events publishes for each rule
+pub const DEFAULT_NAMESPACE: &str = "AWS/Events";
+
+/// dimension identifying the rule each Cloud Watch events metric belongs to
+pub const DEFAULT_DIMENSION_NAME: &str = "RuleName";
+
+/// the window, granularity, and source of rule trigger metrics
+#[derive(Clone, Debug)]
+pub struct MetricQuery {
+    /// how far back from now to look
+    pub since: Duration,
+    /// width of each datapoint's bucket
+    pub period: Duration,
+    /// how datapoints within each bucket are aggregated
+    pub statistic: Statistic,
+    /// namespace to query rule metrics from, e.g. AWS/Events
+    pub namespace: String,
+    /// dimension whose value is the rule name, e.g. RuleName
+    pub dimension_name: String,
+    /// when the window ends, defaulting to now
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// cloudwatch's supported metric statistics
+/// https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/cloudwatch_concepts.html#Statistic
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Statistic {
+    SampleCount,
+    Average,
+    Sum,
+    Minimum,
+    Maximum,
+}
+
+impl Statistic {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Statistic::SampleCount => "SampleCount",
+            Statistic::Average => "Average",
+            Statistic::Sum => "Sum",
+            Statistic::Minimum => "Minimum",
+            Statistic::Maximum => "Maximum",
+        }
+    }
+
+    /// combine the datapoints of a window into this statistic across the
+    /// whole window, e.g. the largest Maximum of any bucket. averages are
+    /// weighted by each bucket's sample count
+    pub fn across(
+        self,
+        datapoints: &[Datapoint],
+    ) -> Option<f64> {
+        let values = |value: fn(&Datapoint) -> Option<f64>| datapoints.iter().filter_map(value);
+        match self {
+            Statistic::SampleCount => Some(values(|dp| dp.sample_count).sum()),
+            Statistic::Sum => Some(values(|dp| dp.sum).sum()),
+            Statistic::Minimum => values(|dp| dp.minimum).reduce(f64::min),
+            Statistic::Maximum => values(|dp| dp.maximum).reduce(f64::max),
+            Statistic::Average => {
+                let samples = values(|dp| dp.sample_count).sum::<f64>();
+                Some(values(|dp| dp.sum).sum::<f64>() / samples).filter(|_| samples > 0.0)
+            }
+        }
+    }
+}
+
+impl FromStr for Statistic {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "SampleCount" => Ok(Statistic::SampleCount),
+            "Average" => Ok(Statistic::Average),
+            "Sum" => Ok(Statistic::Sum),
+            "Minimum" => Ok(Statistic::Minimum),
+            "Maximum" => Ok(Statistic::Maximum),
+            _ => Err(format!(
+                "invalid statistic '{}': expected one of SampleCount, Average, Sum, Minimum, or Maximum",
+                value
+            )),
+        }
+    }
+}
+
+impl MetricQuery {
+    /// the equal length window immediately preceding this one
+    pub fn previous(&self) -> Self {
+        MetricQuery {
+            end: Some(self.end.unwrap_or_else(Utc::now) - self.since),
+            ..self.clone()
+        }
+    }
+
+    /// cloudwatch rejects periods shorter than a second
+    pub fn validate(&self) -> Result<(), String> {
+        if self.period.num_seconds() <= 0 {
+            return Err("metric period must be at least 1s".into());
+        }
+        Ok(())
+    }
+
+    /// the start and end of each sub-window of at most `MAX_DATAPOINTS`
+    /// periods the window is split into, as cloudwatch truncates queries
+    /// covering more datapoints than that
+    pub fn windows(&self) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let end = self.end.unwrap_or_else(Utc::now);
+        let width = self.period * MAX_DATAPOINTS as i32;
+        let mut windows = Vec::new();
+        let mut start = end - self.since;
+        while start < end {
+            let next = (start + width).min(end);
+            windows.push((start, next));
+            start = next;
+        }
+        windows
+    }
+}
+
+/// desired task statuses to list
+#[derive(Clone, Copy, Debug)]
+pub enum Status {
+    Stopped,
+    Running,
+    All,
+}
+
+impl Status {
+    pub fn desired_statuses(self) -> &'static [&'static str] {
+        match self {
+            Status::Stopped => &["STOPPED"],
+            Status::Running => &["RUNNING"],
+            Status::All => &["RUNNING", "STOPPED"],
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "stopped" => Ok(Status::Stopped),
+            "running" => Ok(Status::Running),
+            "all" => Ok(Status::All),
+            _ => Err(format!(
+                "invalid status '{}': expected one of stopped, running, or all",
+                value
+            )),
+        }
+    }
+}
+
+/// timezone used when rendering timestamps
+#[derive(Clone, Copy)]
+pub enum Timezone {
+    Utc,
+    Local,
+    Named(Tz),
+}
+
+impl Timezone {
+    /// render a point in time as an RFC3339 timestamp in this timezone
+    pub fn format<T: TimeZone>(
+        self,
+        time: &DateTime<T>,
+    ) -> String {
+        match self {
+            Timezone::Utc => time
+                .with_timezone(&Utc)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            Timezone::Local => time
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            Timezone::Named(tz) => time
+                .with_timezone(&tz)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+        }
+    }
+
+    /// render an RFC3339 timestamp in this timezone, passing through
+    /// values we can't parse untouched
+    pub fn format_rfc3339(
+        self,
+        timestamp: &str,
+    ) -> String {
+        DateTime::parse_from_rfc3339(timestamp)
+            .map(|time| self.format(&time))
+            .unwrap_or_else(|_| timestamp.into())
+    }
+
+    /// render fractional epoch seconds, as returned by ecs, in this timezone
+    pub fn format_epoch(
+        self,
+        seconds: f64,
+    ) -> Option<String> {
+        Utc.timestamp_opt(seconds.trunc() as i64, (seconds.fract() * 1e9) as u32)
+            .single()
+            .map(|time| self.format(&time))
+    }
+}
+
+impl FromStr for Timezone {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "utc" | "UTC" => Ok(Timezone::Utc),
+            "local" => Ok(Timezone::Local),
+            name => name.parse::<Tz>().map(Timezone::Named).map_err(|_| {
+                format!(
+                    "invalid timezone '{}': expected local, utc, or a name like America/New_York",
+                    name
+                )
+            }),
+        }
+    }
+}
+
+/// which rule states to include
+#[derive(Clone, Copy, Debug)]
+pub enum RuleState {
+    Enabled,
+    Disabled,
+    All,
+}
+
+impl RuleState {
+    pub fn matches(
+        self,
+        state: Option<&str>,
+    ) -> bool {
+        match self {
+            RuleState::Enabled => state == Some("ENABLED"),
+            RuleState::Disabled => state == Some("DISABLED"),
+            RuleState::All => true,
+        }
+    }
+}
+
+impl FromStr for RuleState {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "enabled" => Ok(RuleState::Enabled),
+            "disabled" => Ok(RuleState::Disabled),
+            "all" => Ok(RuleState::All),
+            _ => Err(format!(
+                "invalid state '{}': expected one of enabled, disabled, or all",
+                value
+            )),
+        }
+    }
+}
+
+/// a `key=value` predicate on a rule's tags
+#[derive(Clone, Debug)]
+pub struct TagFilter {
+    pub key: String,
+    pub value: String,
+}
+
+impl TagFilter {
+    pub fn matches(
+        &self,
+        tags: &HashMap<String, String>,
+    ) -> bool {
+        tags.get(&self.key) == Some(&self.value)
+    }
+}
+
+impl FromStr for TagFilter {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once('=') {
+            Some((key, tag_value)) if !key.is_empty() => Ok(TagFilter {
+                key: key.into(),
+                value: tag_value.into(),
+            }),
+            _ => Err(format!("invalid tag '{}': expected key=value", value)),
+        }
+    }
+}
+
+/// a Cloud Watch event rule
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Rule {
+    pub name: String,
+    pub arn: Option<String>,
+    /// the event bus the rule belongs to, the default bus when unset
+    pub event_bus_name: Option<String>,
+    pub state: Option<String>,
+    pub schedule_expression: Option<String>,
+    /// arns of the task definitions the rule's ecs targets launch
+    #[serde(default)]
+    pub task_definitions: Vec<String>,
+    /// arns of the rule's task definitions which were deleted or are
+    /// inactive, so the rule triggers without ever starting a task
+    #[serde(default)]
+    pub broken_targets: Vec<String>,
+    /// arns of the lambda functions the rule targets
+    #[serde(default)]
+    pub lambda_functions: Vec<String>,
+    /// tasks the rule started which were listed but couldn't be described,
+    /// e.g. because they aged out
+    #[serde(default)]
+    pub task_failures: Vec<TaskFailure>,
+    /// arns of tasks the rule started which are still running, looked up
+    /// when only stopped tasks were listed and none were found for a recent
+    /// trigger, as its task may not have stopped yet
+    #[serde(default)]
+    pub running_tasks: Vec<String>,
+}
+
+/// a task ecs listed but failed to describe
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TaskFailure {
+    pub cluster: String,
+    pub arn: Option<String>,
+    pub reason: Option<String>,
+    pub detail: Option<String>,
+}
+
+impl Rule {
+    pub fn target_type(&self) -> TargetType {
+        match (
+            self.task_definitions.is_empty(),
+            self.lambda_functions.is_empty(),
+        ) {
+            (false, true) => TargetType::Ecs,
+            (true, false) => TargetType::Lambda,
+            (false, false) => TargetType::Mixed,
+            (true, true) => TargetType::Other,
+        }
+    }
+}
+
+/// the kinds of target a rule invokes
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetType {
+    Ecs,
+    Lambda,
+    /// both ecs tasks and lambda functions
+    Mixed,
+    /// neither ecs tasks nor lambda functions, e.g. sns topics, or no
+    /// targets at all
+    Other,
+}
+
+impl TargetType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TargetType::Ecs => "ecs",
+            TargetType::Lambda => "lambda",
+            TargetType::Mixed => "mixed",
+            TargetType::Other => "other",
+        }
+    }
+}
+
+/// invocations of a rule's lambda functions within a window
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LambdaInvocations {
+    pub count: u64,
+    /// invocations which failed with a function error
+    pub errors: u64,
+}
+
+/// a summary of when and how often a rule triggered within a window
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Triggers {
+    /// timestamp of the most recent trigger
+    pub last: Option<String>,
+    /// total number of triggers
+    pub count: u64,
+    /// total number of triggers which failed to invoke the rule's target
+    pub failed_invocations: u64,
+    /// total number of triggers in the preceding window of the same length,
+    /// when requested
+    #[serde(default)]
+    pub previous_count: Option<u64>,
+    /// number of triggers on each day of the window, oldest first
+    #[serde(default)]
+    pub daily: Vec<u64>,
+    /// invocations of the rule's lambda functions, when it targets any
+    #[serde(default)]
+    pub lambda: Option<LambdaInvocations>,
+    /// most triggers in a single minute
+    #[serde(default)]
+    pub max_burst: Option<u64>,
+    /// the query's statistic of triggers across the window
+    #[serde(default)]
+    pub statistic: Option<f64>,
+    /// start of the last minute the rule triggered in, when that may have
+    /// been recent enough for ecs to still list the task it started
+    #[serde(default)]
+    pub recent: Option<String>,
+}
+
+impl Triggers {
+    /// `recent`, parsed
+    fn recent_trigger(&self) -> Option<DateTime<Utc>> {
+        self.recent
+            .as_deref()
+            .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+            .map(|ts| ts.with_timezone(&Utc))
+    }
+}
+
+/// a rule we failed to gather the history of
+#[derive(Clone, Debug, Serialize)]
+pub struct RuleError {
+    pub rule: String,
+    pub error: String,
+    /// true when the rule was abandoned at the run's deadline, or for
+    /// exceeding the per rule timeout
+    pub timed_out: bool,
+    /// true when the rule was abandoned because the run was interrupted
+    pub interrupted: bool,
+}
+
+impl RuleError {
+    pub fn new(
+        rule: &Rule,
+        error: CronitorError,
+    ) -> Self {
+        RuleError {
+            rule: rule.name.clone(),
+            error: error.to_string(),
+            timed_out: error.timed_out(),
+            interrupted: error.interrupted(),
+        }
+    }
+}
+
+/// fail an operation with `CronitorError::TimedOut` if it doesn't complete
+/// by a deadline, or `CronitorError::Interrupted` once the run is
+/// interrupted
+pub async fn within<F, T>(
+    deadline: Option<Instant>,
+    operation: F,
+) -> Result<T, CronitorError>
+where
+    F: Future<Output = Result<T, CronitorError>>,
+{
+    let operation = async {
+        match deadline {
+            Some(deadline) => timeout_at(deadline, operation)
+                .await
+                .unwrap_or(Err(CronitorError::TimedOut)),
+            _ => operation.await,
+        }
+    };
+    tokio::select! {
+        result = operation => result,
+        _ = interruption() => Err(CronitorError::Interrupted),
+    }
+}
+
+/// whether the run was interrupted, see `interrupt`
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// wakes operations waiting on an interruption
+static INTERRUPTION: Notify = Notify::const_new();
+
+/// abandon every rule's remaining operations, and any started after, so a
+/// run can report what it gathered before it was interrupted
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    INTERRUPTION.notify_waiters();
+}
+
+/// complete once the run is interrupted
+async fn interruption() {
+    let notified = INTERRUPTION.notified();
+    if !INTERRUPTED.load(Ordering::SeqCst) {
+        notified.await;
+    }
+}
+
+/// when to abandon rules: all of them at the run's deadline, or any one
+/// whose calls to a single api take longer than the per rule timeout
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Deadline {
+    pub run: Option<Instant>,
+    pub rule: Option<StdDuration>,
+}
+
+impl Deadline {
+    /// fail one rule's operation with `CronitorError::TimedOut` at the run's
+    /// deadline, or `CronitorError::RuleTimedOut` if it takes too long itself
+    pub async fn within<F, T>(
+        self,
+        operation: F,
+    ) -> Result<T, CronitorError>
+    where
+        F: Future<Output = Result<T, CronitorError>>,
+    {
+        let operation = within(self.run, operation);
+        match self.rule {
+            Some(limit) => timeout(limit, operation)
+                .await
+                .unwrap_or(Err(CronitorError::RuleTimedOut(limit))),
+            _ => operation.await,
+        }
+    }
+}
+
+/// a stable, serializable summary of a rule and the tasks it has started
+#[derive(Clone, Serialize)]
+pub struct RuleReport {
+    pub rule: String,
+    pub arn: Option<String>,
+    pub state: Option<String>,
+    /// arns of the task definitions the rule's ecs targets launch, empty
+    /// when the rule has no ecs target, e.g. because it was deleted
+    pub task_definitions: Vec<String>,
+    /// arns of the rule's task definitions which were deleted or are
+    /// inactive
+    pub broken_targets: Vec<String>,
+    pub target_type: TargetType,
+    /// arns of the lambda functions the rule targets
+    pub lambda_functions: Vec<String>,
+    pub last_trigger: Option<String>,
+    pub trigger_count: u64,
+    /// most triggers in a single minute
+    pub max_burst: Option<u64>,
+    /// the --statistic of triggers across the window, e.g. the largest
+    /// Maximum of any bucket
+    pub trigger_statistic: Option<f64>,
+    /// number of triggers on each day of the window, oldest first
+    pub daily_triggers: Vec<u64>,
+    pub failed_invocations: u64,
+    /// invocations of the rule's lambda functions, or `None` when it
+    /// targets none
+    pub lambda_invocations: Option<u64>,
+    /// lambda invocations which failed with a function error
+    pub lambda_errors: Option<u64>,
+    /// percent of triggers which started tasks or invoked functions that
+    /// didn't fail, or `None` when the rule hasn't triggered
+    pub success_rate: Option<f64>,
+    /// how the trigger count changed from the preceding window, when
+    /// requested
+    pub trend: Option<Trend>,
+    pub stale: bool,
+    pub schedule: Option<String>,
+    pub next_run: Option<String>,
+    /// true when the schedule says the rule should have triggered since its
+    /// last trigger but hasn't
+    pub overdue: bool,
+    /// arns of the rule's running tasks when more than one is running at
+    /// once, empty otherwise
+    pub overlapping_tasks: Vec<String>,
+    /// true when the rule recently triggered its ecs target but no task it
+    /// started was found, i.e. the schedule fires but ecs launches nothing
+    pub triggered_without_task: bool,
+    /// the rule's page in the AWS console
+    pub console_url: String,
+    /// mean run time of the rule's tasks which started and stopped, or
+    /// `None` when there are none
+    pub average_duration_seconds: Option<f64>,
+    /// number of the rule's stopped containers which exited with each exit
+    /// code, e.g. 137 when killed for running out of memory
+    pub exit_codes: BTreeMap<i64, usize>,
+    pub stopped_tasks: Vec<StoppedTask>,
+    /// tasks which were listed but couldn't be described, explaining why
+    /// fewer tasks are reported than ecs listed
+    pub task_failures: Vec<TaskFailure>,
+}
+
+/// percent drop in triggers from the preceding window considered significant
+pub const TREND_DROP_PERCENT: f64 = 25.0;
+
+/// how a rule's trigger count changed from the preceding window of the
+/// same length
+#[derive(Clone, Serialize)]
+pub struct Trend {
+    pub previous_count: u64,
+    pub delta: i64,
+    /// percent change from the preceding window, or `None` when it had no
+    /// triggers to divide by
+    pub change_percent: Option<f64>,
+    /// true when the count dropped by at least `TREND_DROP_PERCENT`
+    pub dropped: bool,
+}
+
+impl Trend {
+    pub fn new(
+        previous_count: u64,
+        count: u64,
+    ) -> Self {
+        let change_percent = Some(previous_count)
+            .filter(|previous| *previous > 0)
+            .map(|previous| (count as f64 - previous as f64) / previous as f64 * 100.0);
+        Trend {
+            previous_count,
+            delta: count as i64 - previous_count as i64,
+            change_percent,
+            dropped: change_percent
+                .map(|change| change <= -TREND_DROP_PERCENT)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct StoppedTask {
+    pub task_arn: Option<String>,
+    pub task_definition_arn: Option<String>,
+    /// name of the cluster the task ran in
+    pub cluster: Option<String>,
+    /// the task's page in the AWS console
+    pub console_url: Option<String>,
+    pub last_status: Option<String>,
+    pub stopped_reason: Option<String>,
+    pub started_at: Option<String>,
+    pub stopped_at: Option<String>,
+    /// seconds between the task starting and stopping, or `None` when it's
+    /// still running or never started
+    pub duration_seconds: Option<f64>,
+    /// cpu units reserved for the task
+    pub cpu: Option<String>,
+    /// MiB of memory reserved for the task
+    pub memory: Option<String>,
+    /// true when the task or any of its containers stopped for running out
+    /// of memory
+    pub out_of_memory: bool,
+    /// how the task was launched, one of EC2, FARGATE, or EXTERNAL
+    pub launch_type: Option<String>,
+    /// the capacity provider the task ran on, e.g. FARGATE_SPOT
+    pub capacity_provider: Option<String>,
+    /// true when the task was stopped because its spot capacity was
+    /// reclaimed rather than failing on its own
+    pub spot_interrupted: bool,
+    pub containers: Vec<ContainerExit>,
+}
+
+/// how a single container within a task exited
+#[derive(Clone, Serialize)]
+pub struct ContainerExit {
+    pub name: Option<String>,
+    pub exit_code: Option<i64>,
+    pub reason: Option<String>,
+    /// cpu units reserved for the container
+    pub cpu: Option<String>,
+    /// hard limit of MiB of memory the container may use
+    pub memory: Option<String>,
+    /// soft limit of MiB of memory reserved for the container
+    pub memory_reservation: Option<String>,
+    /// the tail of the container's logs, fetched only for failed containers
+    /// when requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub logs: Vec<String>,
+}
+
+impl StoppedTask {
+    pub fn new(
+        task: &Task,
+        region: &Region,
+        timezone: Timezone,
+    ) -> Self {
+        let cluster = task.cluster_arn.as_deref().and_then(cluster_name);
+        let task_id = task
+            .task_arn
+            .as_deref()
+            .and_then(|arn| arn.rsplit('/').next());
+        StoppedTask {
+            task_arn: task.task_arn.clone(),
+            task_definition_arn: task.task_definition_arn.clone(),
+            cluster: cluster.map(String::from),
+            console_url: cluster
+                .zip(task_id)
+                .map(|(cluster, task_id)| task_console_url(region, cluster, task_id)),
+            last_status: task.last_status.clone(),
+            stopped_reason: task.stopped_reason.clone(),
+            started_at: task.started_at.and_then(|at| timezone.format_epoch(at)),
+            stopped_at: task.stopped_at.and_then(|at| timezone.format_epoch(at)),
+            duration_seconds: task
+                .started_at
+                .zip(task.stopped_at)
+                .map(|(started_at, stopped_at)| (stopped_at - started_at).max(0.0)),
+            cpu: task.cpu.clone(),
+            memory: task.memory.clone(),
+            out_of_memory: task
+                .stopped_reason
+                .iter()
+                .chain(task.containers.iter().flatten().flat_map(|c| &c.reason))
+                .any(|reason| out_of_memory(reason)),
+            launch_type: task.launch_type.clone(),
+            capacity_provider: task.capacity_provider_name.clone(),
+            spot_interrupted: task.stop_code.as_deref() == Some("SpotInterruption"),
+            containers: task
+                .containers
+                .iter()
+                .flatten()
+                .map(|container| ContainerExit {
+                    name: container.name.clone(),
+                    exit_code: container.exit_code,
+                    reason: container.reason.clone(),
+                    cpu: container.cpu.clone(),
+                    memory: container.memory.clone(),
+                    memory_reservation: container.memory_reservation.clone(),
+                    logs: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    /// true when the task has stopped running
+    pub fn stopped(&self) -> bool {
+        self.last_status.as_deref() == Some("STOPPED")
+    }
+
+    /// true when the task is still running
+    pub fn running(&self) -> bool {
+        self.last_status.as_deref() == Some("RUNNING")
+    }
+
+    /// true when any container exited with a non-zero exit code, other
+    /// than because its spot capacity was reclaimed
+    pub fn failed(&self) -> bool {
+        !self.spot_interrupted && self.containers.iter().any(ContainerExit::failed)
+    }
+}
+
+impl ContainerExit {
+    /// true when the container exited with a non-zero exit code
+    pub fn failed(&self) -> bool {
+        self.exit_code.map(|code| code != 0).unwrap_or_default()
+    }
+}
+
+impl RuleReport {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        rule: Rule,
+        triggers: Triggers,
+        tasks: &[Task],
+        status: Status,
+        region: &Region,
+        query: &MetricQuery,
+        max_age: Option<Duration>,
+        timezone: Timezone,
+    ) -> Self {
+        let now = Utc::now();
+        let stale = max_age
+            .map(|max_age| is_stale(triggers.last.as_deref(), query, max_age, now))
+            .unwrap_or_default();
+        let schedule = rule
+            .schedule_expression
+            .as_deref()
+            .and_then(|expression| match expression.parse::<Schedule>() {
+                Ok(schedule) => Some(schedule),
+                Err(err) => {
+                    warn!("unable to parse schedule of rule {}: {}", rule.name, err);
+                    None
+                }
+            });
+        let last_trigger = triggers
+            .last
+            .as_deref()
+            .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+            .map(|ts| ts.with_timezone(&Utc));
+        let next_run = schedule
+            .as_ref()
+            .and_then(|schedule| schedule.next_run(last_trigger, now))
+            .map(|ts| timezone.format(&ts));
+        let console_url = rule_console_url(region, rule.event_bus_name.as_deref(), &rule.name);
+        let stopped_tasks = tasks
+            .iter()
+            .map(|task| StoppedTask::new(task, region, timezone))
+            .collect::<Vec<_>>();
+        let failed_tasks = stopped_tasks.iter().filter(|task| task.failed()).count();
+        let lambda_errors = triggers.lambda.as_ref().map(|lambda| lambda.errors);
+        let success_rate = success_rate(
+            triggers.count,
+            triggers.failed_invocations + failed_tasks as u64 + lambda_errors.unwrap_or_default(),
+        );
+        let target_type = rule.target_type();
+        let overdue = schedule
+            .as_ref()
+            .map(|schedule| is_overdue(schedule, last_trigger, query, now))
+            .unwrap_or_default();
+        let running = stopped_tasks
+            .iter()
+            .filter(|task| task.running())
+            .filter_map(|task| task.task_arn.clone())
+            .collect::<Vec<_>>();
+        let count = triggers.count;
+        let trend = triggers
+            .previous_count
+            .map(|previous| Trend::new(previous, count));
+        let durations = stopped_tasks
+            .iter()
+            .filter_map(|task| task.duration_seconds)
+            .collect::<Vec<_>>();
+        let average_duration_seconds = Some(durations.len())
+            .filter(|count| *count > 0)
+            .map(|count| durations.iter().sum::<f64>() / count as f64);
+        let mut exit_codes = BTreeMap::new();
+        for code in stopped_tasks
+            .iter()
+            .flat_map(|task| &task.containers)
+            .filter_map(|container| container.exit_code)
+        {
+            *exit_codes.entry(code).or_default() += 1;
+        }
+        let overlapping_tasks = if running.len() > 1 {
+            running
+        } else {
+            Vec::new()
+        };
+        let triggered_without_task = matches!(target_type, TargetType::Ecs | TargetType::Mixed)
+            && triggers.count > triggers.failed_invocations
+            && tasks.is_empty()
+            && rule.task_failures.is_empty()
+            && rule.running_tasks.is_empty()
+            && triggered_within_retention(triggers.recent_trigger(), status, now);
+        RuleReport {
+            rule: rule.name,
+            arn: rule.arn,
+            state: rule.state,
+            task_definitions: rule.task_definitions,
+            broken_targets: rule.broken_targets,
+            target_type,
+            lambda_functions: rule.lambda_functions,
+            last_trigger: triggers.last.map(|ts| timezone.format_rfc3339(&ts)),
+            trigger_count: triggers.count,
+            max_burst: triggers.max_burst,
+            trigger_statistic: triggers.statistic,
+            daily_triggers: triggers.daily,
+            failed_invocations: triggers.failed_invocations,
+            lambda_invocations: triggers.lambda.as_ref().map(|lambda| lambda.count),
+            lambda_errors,
+            success_rate,
+            trend,
+            stale,
+            schedule: rule.schedule_expression,
+            next_run,
+            overdue,
+            overlapping_tasks,
+            triggered_without_task,
+            console_url,
+            average_duration_seconds,
+            exit_codes,
+            stopped_tasks,
+            task_failures: rule.task_failures,
+        }
+    }
+}
+
+/// how long ecs lists tasks after they've stopped, at minimum
+/// https://docs.aws.amazon.com/AmazonECS/latest/APIReference/API_ListTasks.html
+const STOPPED_TASK_RETENTION_MINUTES: i64 = 60;
+
+/// true when a task started by a rule's last trigger should still be listed,
+/// as older stopped tasks may have been forgotten by ecs rather than never
+/// started, given the start of the minute it triggered in
+fn triggered_within_retention(
+    last_trigger: Option<DateTime<Utc>>,
+    status: Status,
+    now: DateTime<Utc>,
+) -> bool {
+    match (last_trigger, status) {
+        // a task which has already stopped isn't listed
+        (_, Status::Running) => false,
+        (Some(last), _) => now - last < Duration::minutes(STOPPED_TASK_RETENTION_MINUTES),
+        _ => false,
+    }
+}
+
+/// true when a task or container stopped reason looks like it ran out of
+/// memory, e.g. "OutOfMemoryError: Container killed due to memory usage"
+fn out_of_memory(reason: &str) -> bool {
+    let reason = reason.to_ascii_lowercase();
+    reason.contains("outofmemory")
+        || reason.contains("out of memory")
+        || reason
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|word| word == "oom" || word == "oomkilled")
+}
+
+/// extract the family from a task definition arn,
+/// e.g. arn:aws:ecs:region:account:task-definition/family:revision
+pub fn task_family(task_definition_arn: &str) -> Option<&str> {
+    let name = task_definition_arn.rsplit('/').next()?;
+    name.split(':').next().filter(|family| !family.is_empty())
+}
+
+/// the percent of triggers which didn't fail, or `None` when there were no
+/// triggers to divide by
+pub fn success_rate(
+    triggers: u64,
+    failures: u64,
+) -> Option<f64> {
+    if triggers == 0 {
+        return None;
+    }
+    let succeeded = triggers.saturating_sub(failures);
+    Some(succeeded as f64 / triggers as f64 * 100.0)
+}
+
+/// the base url of the AWS console serving a region
+fn console_base(region: &Region) -> String {
+    let name = region.name();
+    let domain = if name.starts_with("cn-") {
+        "console.amazonaws.cn"
+    } else if name.starts_with("us-gov-") {
+        "console.amazonaws-us-gov.com"
+    } else {
+        "console.aws.amazon.com"
+    };
+    format!("https://{}.{}", name, domain)
+}
+
+/// a rule's page in the AWS console, on the default event bus unless
+/// another is given
+pub fn rule_console_url(
+    region: &Region,
+    bus: Option<&str>,
+    rule: &str,
+) -> String {
+    format!(
+        "{}/events/home?region={}#/eventbus/{}/rules/{}",
+        console_base(region),
+        region.name(),
+        bus.unwrap_or("default"),
+        rule
+    )
+}
+
+/// a task's page in the AWS console
+pub fn task_console_url(
+    region: &Region,
+    cluster: &str,
+    task_id: &str,
+) -> String {
+    format!(
+        "{}/ecs/v2/clusters/{}/tasks/{}?region={}",
+        console_base(region),
+        cluster,
+        task_id,
+        region.name()
+    )
+}
+
+/// extract the name from a cluster arn,
+/// e.g. arn:aws:ecs:region:account:cluster/name
+pub fn cluster_name(cluster_arn: &str) -> Option<&str> {
+    cluster_arn
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+}
+
+/// counts of rule health across a set of reports
+#[derive(Clone, Serialize)]
+pub struct Summary {
+    pub rules: usize,
+    pub errors: usize,
+    pub stale: usize,
+    pub overdue: usize,
+    /// rules with more than one task running at once
+    pub overlapping: usize,
+    /// rules with a deleted or inactive target
+    pub with_broken_targets: usize,
+    /// rules which triggered but started no task
+    pub without_tasks: usize,
+    pub with_stopped_tasks: usize,
+    pub with_failed_tasks: usize,
+}
+
+impl Summary {
+    pub fn new(
+        reports: &[RuleReport],
+        errors: &[RuleError],
+    ) -> Self {
+        Summary {
+            rules: reports.len() + errors.len(),
+            errors: errors.len(),
+            stale: reports.iter().filter(|report| report.stale).count(),
+            overdue: reports.iter().filter(|report| report.overdue).count(),
+            overlapping: reports
+                .iter()
+                .filter(|report| !report.overlapping_tasks.is_empty())
+                .count(),
+            with_broken_targets: reports
+                .iter()
+                .filter(|report| !report.broken_targets.is_empty())
+                .count(),
+            without_tasks: reports
+                .iter()
+                .filter(|report| report.triggered_without_task)
+                .count(),
+            with_stopped_tasks: reports
+                .iter()
+                .filter(|report| report.stopped_tasks.iter().any(StoppedTask::stopped))
+                .count(),
+            with_failed_tasks: reports
+                .iter()
+                .filter(|report| report.stopped_tasks.iter().any(StoppedTask::failed))
+                .count(),
+        }
+    }
+}
+
+/// rules rolled up by the task definition family they launch
+#[derive(Serialize)]
+pub struct FamilyGroup<'a> {
+    /// the families the rules launch, or `None` for a single rule whose
+    /// family isn't known, e.g. one targeting lambda
+    pub family: Option<String>,
+    pub trigger_count: u64,
+    pub failed_invocations: u64,
+    pub failed_tasks: usize,
+    pub stale: usize,
+    pub rules: Vec<&'a RuleReport>,
+}
+
+/// group reports by the families of their task definitions in the order
+/// they're first seen, leaving rules without one in groups of their own
+pub fn group_by_family(reports: &[RuleReport]) -> Vec<FamilyGroup<'_>> {
+    let mut groups: Vec<FamilyGroup> = Vec::new();
+    let mut index = HashMap::new();
+    for report in reports {
+        let families = report
+            .task_definitions
+            .iter()
+            .filter_map(|arn| task_family(arn))
+            .collect::<BTreeSet<_>>();
+        let family = Some(families)
+            .filter(|families| !families.is_empty())
+            .map(|families| families.into_iter().collect::<Vec<_>>().join(", "));
+        let position = match family.as_ref().and_then(|family| index.get(family)) {
+            Some(position) => *position,
+            _ => {
+                if let Some(family) = &family {
+                    index.insert(family.clone(), groups.len());
+                }
+                groups.push(FamilyGroup {
+                    family,
+                    trigger_count: 0,
+                    failed_invocations: 0,
+                    failed_tasks: 0,
+                    stale: 0,
+                    rules: Vec::new(),
+                });
+                groups.len() - 1
+            }
+        };
+        let group = &mut groups[position];
+        group.trigger_count += report.trigger_count;
+        group.failed_invocations += report.failed_invocations;
+        group.failed_tasks += report
+            .stopped_tasks
+            .iter()
+            .filter(|task| task.failed())
+            .count();
+        group.stale += report.stale as usize;
+        group.rules.push(report);
+    }
+    groups
+}
+
+impl fmt::Display for Summary {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        write!(
+            f,
+            "{} rules checked, {} stale, {} overdue, {} overlapping, {} with broken targets, {} triggered without tasks, {} with stopped tasks, {} with non-zero exit codes, {} failed to query",
+            self.rules,
+            self.stale,
+            self.overdue,
+            self.overlapping,
+            self.with_broken_targets,
+            self.without_tasks,
+            self.with_stopped_tasks,
+            self.with_failed_tasks,
+            self.errors
+        )
+    }
+}
+
+/// the full set of rule reports along with their summary and the rules we
+/// failed to report on
+#[derive(Serialize)]
+pub struct Report {
+    pub rules: Vec<RuleReport>,
+    pub errors: Vec<RuleError>,
+    pub summary: Summary,
+}
+
+impl Report {
+    pub fn new(
+        rules: Vec<RuleReport>,
+        errors: Vec<RuleError>,
+    ) -> Self {
+        let summary = Summary::new(&rules, &errors);
+        Report {
+            rules,
+            errors,
+            summary,
+        }
+    }
+}
+
+/// a rule is stale when it has not triggered within max_age, including
+/// when it has not triggered at all
+///
+/// trigger timestamps mark the start of their datapoint's bucket, and the
+/// trigger may have come as late as the bucket's end, so age is measured
+/// from the end of the bucket, or from now while it's still open
+pub fn is_stale(
+    last_trigger: Option<&str>,
+    query: &MetricQuery,
+    max_age: Duration,
+    now: DateTime<Utc>,
+) -> bool {
+    match last_trigger.map(DateTime::parse_from_rfc3339) {
+        Some(Ok(time)) => now - (time.with_timezone(&Utc) + query.period).min(now) > max_age,
+        _ => true,
+    }
+}
+
+/// how long to allow for a trigger to be reported to cloudwatch before
+/// considering a rule overdue
+const OVERDUE_GRACE_MINUTES: i64 = 15;
+
+/// a rule is overdue when its schedule should have fired since the bucket of
+/// its last trigger, or within the query window if it hasn't triggered at all
+pub fn is_overdue(
+    schedule: &Schedule,
+    last_trigger: Option<DateTime<Utc>>,
+    query: &MetricQuery,
+    now: DateTime<Utc>,
+) -> bool {
+    let from = match last_trigger {
+        // trigger timestamps mark the start of their datapoint's bucket
+        Some(last) => last + query.period,
+        _ => now - query.since,
+    };
+    schedule
+        .next_from(from)
+        .map(|expected| expected + Duration::minutes(OVERDUE_GRACE_MINUTES) < now)
+        .unwrap_or_default()
+}
+
+/// credentials resolved from either a named profile, instance or container
+/// metadata, or the default provider chain, optionally used to assume a role
+#[derive(Clone)]
+pub enum Credentials {
+    Chain(Box<ChainProvider>),
+    Profile(ProfileProvider),
+    InstanceMetadata(Arc<AutoRefreshingProvider<Imdsv2Provider>>),
+    ContainerMetadata(Arc<AutoRefreshingProvider<ContainerProvider>>),
+    AssumeRole(Arc<AutoRefreshingProvider<StsAssumeRoleSessionCredentialsProvider>>),
+}
+
+#[async_trait]
+impl ProvideAwsCredentials for Credentials {
+    async fn credentials(&self) -> Result<AwsCredentials, CredentialsError> {
+        match self {
+            Credentials::Chain(chain) => chain.credentials().await,
+            Credentials::Profile(profile) => profile.credentials().await,
+            Credentials::InstanceMetadata(metadata) => metadata.credentials().await,
+            Credentials::ContainerMetadata(metadata) => metadata.credentials().await,
+            Credentials::AssumeRole(role) => role.credentials().await,
+        }
+    }
+}
+
+/// environment variable the ecs agent sets to the path of a task's
+/// credentials on the container credentials endpoint
+/// https://docs.aws.amazon.com/AmazonECS/latest/developerguide/task-iam-roles.html
+const CONTAINER_CREDENTIALS_URI: &str = "AWS_CONTAINER_CREDENTIALS_RELATIVE_URI";
+
+/// resolve credentials from a named profile, from metadata when
+/// `metadata_timeout` is set, or else from the default provider chain,
+/// failing when the profile's credentials file can't be located
+///
+/// metadata is fetched from the ecs container credentials endpoint when
+/// running in a task, where instance metadata is often unreachable, or else
+/// from instance metadata
+pub fn credentials(
+    profile: Option<String>,
+    timeout: StdDuration,
+    metadata_timeout: Option<StdDuration>,
+) -> Result<Credentials, CronitorError> {
+    let credentials = match (profile, metadata_timeout) {
+        (Some(name), _) => {
+            let mut provider = ProfileProvider::new().map_err(|err| {
+                CronitorError::Credentials(format!("failed to locate aws profiles: {}", err))
+            })?;
+            provider.set_profile(name);
+            Credentials::Profile(provider)
+        }
+        (_, Some(metadata_timeout)) if env::var_os(CONTAINER_CREDENTIALS_URI).is_some() => {
+            let mut provider = ContainerProvider::new();
+            provider.set_timeout(metadata_timeout);
+            Credentials::ContainerMetadata(Arc::new(
+                AutoRefreshingProvider::new(provider).map_err(|err| {
+                    CronitorError::Credentials(format!(
+                        "failed to create container metadata provider: {}",
+                        err
+                    ))
+                })?,
+            ))
+        }
+        (_, Some(metadata_timeout)) => Credentials::InstanceMetadata(Arc::new(
+            AutoRefreshingProvider::new(Imdsv2Provider::new(metadata_timeout)).map_err(|err| {
+                CronitorError::Credentials(format!(
+                    "failed to create instance metadata provider: {}",
+                    err
+                ))
+            })?,
+        )),
+        _ => {
+            let mut chain = ChainProvider::new();
+            chain.set_timeout(timeout);
+            Credentials::Chain(Box::new(chain))
+        }
+    };
+    Ok(credentials)
+}
+
+/// dispatches AWS requests, bounding how long connecting and each request
+/// may take
+#[derive(Clone)]
+pub struct Dispatcher {
+    client: Arc<HttpClient>,
+    /// how long to wait on a response, unless a request sets its own timeout
+    read_timeout: Option<StdDuration>,
+}
+
+impl Dispatcher {
+    pub fn new(
+        connect_timeout: Option<StdDuration>,
+        read_timeout: Option<StdDuration>,
+    ) -> Self {
+        let mut http = HttpConnector::new();
+        http.set_connect_timeout(connect_timeout);
+        http.enforce_http(false);
+        Dispatcher {
+            client: Arc::new(HttpClient::from_connector(
+                HttpsConnector::new_with_connector(http),
+            )),
+            read_timeout,
+        }
+    }
+}
+
+impl DispatchSignedRequest for Dispatcher {
+    fn dispatch(
+        &self,
+        request: SignedRequest,
+        timeout: Option<StdDuration>,
+    ) -> DispatchSignedRequestFuture {
+        self.client.dispatch(request, timeout.or(self.read_timeout))
+    }
+}
+
+/// name of the sessions created when assuming a role
+const ROLE_SESSION_NAME: &str = "cronitor";
+
+/// assume a role using a set of base credentials, refreshing the session
+/// before it expires
+pub fn assume_role(
+    dispatcher: Dispatcher,
+    base: Credentials,
+    region: Region,
+    role_arn: String,
+    external_id: Option<String>,
+) -> Result<Credentials, CronitorError> {
+    let sts = StsClient::new_with(dispatcher, base, region);
+    let provider = StsAssumeRoleSessionCredentialsProvider::new(
+        sts,
+        role_arn,
+        ROLE_SESSION_NAME.into(),
+        external_id,
+        None,
+        None,
+        None,
+    );
+    Ok(Credentials::AssumeRole(Arc::new(
+        AutoRefreshingProvider::new(provider).map_err(|err| {
+            CronitorError::Credentials(format!("failed to create assume role provider: {}", err))
+        })?,
+    )))
+}
+
+/// look up the id of the account a set of credentials belongs to
+pub async fn account_id(
+    dispatcher: Dispatcher,
+    credentials: Credentials,
+    region: Region,
+    retries: &Retries,
+) -> Result<String, CronitorError> {
+    let sts = StsClient::new_with(dispatcher, credentials, region);
+    let identity = retry(retries, || {
+        sts.get_caller_identity(GetCallerIdentityRequest::default())
+    })
+    .await?;
+    identity
+        .account
+        .ok_or_else(|| CronitorError::InvalidResponse("caller identity has no account".into()))
+}
+
+/// base delay between retries of a throttled request, doubled on each attempt
+const RETRY_BASE_DELAY_MILLIS: u64 = 100;
+
+/// upper bound on the delay between retries of a throttled request
+const RETRY_MAX_DELAY_MILLIS: u64 = 10_000;
+
+/// exponential backoff with full jitter
+/// https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/
+fn backoff(attempt: usize) -> StdDuration {
+    let ceiling = RETRY_BASE_DELAY_MILLIS
+        .saturating_mul(1 << attempt.min(16))
+        .min(RETRY_MAX_DELAY_MILLIS);
+    StdDuration::from_millis(thread_rng().gen_range(0, ceiling + 1))
+}
+
+/// a random delay of up to `jitter`
+fn spread(jitter: StdDuration) -> StdDuration {
+    let max = jitter.as_millis() as u64;
+    StdDuration::from_millis(thread_rng().gen_range(0, max + 1))
+}
+
+/// how many times to retry failed requests, and which AWS error codes are
+/// worth retrying
+#[derive(Clone, Debug)]
+pub struct Retries {
+    pub max: usize,
+    /// error codes to retry, e.g. Throttling
+    pub on: Vec<String>,
+}
+
+impl Retries {
+    /// retry throttled requests up to `max` times
+    pub fn throttled(max: usize) -> Self {
+        Retries {
+            max,
+            on: THROTTLE_CODES.iter().map(|code| code.to_string()).collect(),
+        }
+    }
+}
+
+/// whether raw AWS responses are printed to stderr, see `dump_responses`
+static DUMP_RESPONSES: AtomicBool = AtomicBool::new(false);
+
+/// print the raw responses of the rule, metric, and task apis to stderr
+/// before they're transformed, regardless of the log level
+pub fn dump_responses(enabled: bool) {
+    DUMP_RESPONSES.store(enabled, Ordering::Relaxed);
+}
+
+fn dump<T: fmt::Debug>(
+    operation: &str,
+    response: &T,
+) {
+    if DUMP_RESPONSES.load(Ordering::Relaxed) {
+        eprintln!("{} response: {:#?}", operation, response);
+    }
+}
+
+/// retry requests which fail with one of the retried error codes up to
+/// max times, failing fast on any other error
+async fn retry<F, R, T, E>(
+    retries: &Retries,
+    mut action: F,
+) -> Result<T, CronitorError>
+where
+    F: FnMut() -> R,
+    R: Future<Output = Result<T, E>>,
+    CronitorError: From<E>,
+{
+    let mut attempt = 0;
+    loop {
+        match action().await.map_err(CronitorError::from) {
+            Err(ref err) if err.has_code(&retries.on) && attempt < retries.max => {
+                let delay = backoff(attempt);
+                warn!(
+                    "request failed with {}, retrying in {}ms (attempt {} of {})",
+                    err,
+                    delay.as_millis(),
+                    attempt + 1,
+                    retries.max
+                );
+                sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// list all rules in a given state matching a prefix, following pagination
+/// until the last page
+pub async fn list_matching_rules<E: EventsApi>(
+    events: &E,
+    bus: Option<&str>,
+    prefix: &str,
+    state: RuleState,
+) -> Result<Vec<Rule>, CronitorError> {
+    let mut rules = Vec::new();
+    let mut next_token = None;
+    loop {
+        debug!("listing rules matching prefix {}", prefix);
+        let result = events
+            .list_rules(ListRulesRequest {
+                event_bus_name: bus.map(Into::into),
+                name_prefix: Some(prefix.into()),
+                next_token,
+                ..ListRulesRequest::default()
+            })
+            .await?;
+        dump("ListRules", &result);
+        rules.extend(
+            result
+                .rules
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|rule| state.matches(rule.state.as_deref()))
+                .map(|rule| Rule {
+                    name: rule.name.unwrap_or_default(),
+                    arn: rule.arn,
+                    event_bus_name: rule.event_bus_name,
+                    state: rule.state,
+                    schedule_expression: rule.schedule_expression,
+                    task_definitions: Vec::new(),
+                    broken_targets: Vec::new(),
+                    lambda_functions: Vec::new(),
+                    task_failures: Vec::new(),
+                    running_tasks: Vec::new(),
+                }),
+        );
+        match result.next_token {
+            Some(token) => next_token = Some(token),
+            _ => {
+                info!("found {} rules matching prefix {}", rules.len(), prefix);
+                return Ok(rules);
+            }
+        }
+    }
+}
+
+/// look up rules by their exact names rather than listing them by prefix,
+/// keeping those in a given state and failing when any don't exist
+pub async fn describe_rules<E: EventsApi>(
+    events: &E,
+    bus: Option<&str>,
+    names: &[String],
+    state: RuleState,
+    concurrency: usize,
+    retries: &Retries,
+) -> Result<Vec<Rule>, CronitorError> {
+    let described = stream::iter(names.to_vec())
+        .map(|name| async move {
+            let request = DescribeRuleRequest {
+                name: name.clone(),
+                event_bus_name: bus.map(Into::into),
+            };
+            debug!("describing rule {}", name);
+            match retry(retries, || events.describe_rule(request.clone())).await {
+                Ok(rule) => Ok(Some(rule)),
+                Err(CronitorError::NotFound(_)) => Ok(None),
+                Err(err) => Err(err),
+            }
+            .map(|rule| (name, rule))
+        })
+        .buffered(concurrency)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let missing = described
+        .iter()
+        .filter(|(_, rule)| rule.is_none())
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(CronitorError::NotFound(format!(
+            "no rules named {}",
+            missing.join(", ")
+        )));
+    }
+    let rules = described
+        .into_iter()
+        .filter_map(|(_, rule)| rule)
+        .filter(|rule| state.matches(rule.state.as_deref()))
+        .map(|rule| Rule {
+            name: rule.name.unwrap_or_default(),
+            arn: rule.arn,
+            event_bus_name: rule.event_bus_name,
+            state: rule.state,
+            schedule_expression: rule.schedule_expression,
+            task_definitions: Vec::new(),
+            broken_targets: Vec::new(),
+            lambda_functions: Vec::new(),
+            task_failures: Vec::new(),
+            running_tasks: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    info!("found {} of {} named rules", rules.len(), names.len());
+    Ok(rules)
+}
+
+/// make one lightweight call against each api cronitor queries, returning
+/// each api's name along with whether the call succeeded
+pub async fn validate_access<E: EventsApi, M: MetricsApi, C: EcsApi>(
+    events: &E,
+    metrics: &M,
+    ecs: &C,
+) -> Vec<(&'static str, Result<(), CronitorError>)> {
+    let (events_access, metrics_access, ecs_access) = futures::join!(
+        events.list_rules(ListRulesRequest {
+            limit: Some(1),
+            ..ListRulesRequest::default()
+        }),
+        metrics.list_metrics(ListMetricsInput {
+            namespace: Some(DEFAULT_NAMESPACE.into()),
+            ..ListMetricsInput::default()
+        }),
+        ecs.list_clusters(ListClustersRequest {
+            max_results: Some(1),
+            ..ListClustersRequest::default()
+        }),
+    );
+    vec![
+        (
+            "events",
+            events_access.map(drop).map_err(CronitorError::from),
+        ),
+        (
+            "cloudwatch",
+            metrics_access.map(drop).map_err(CronitorError::from),
+        ),
+        ("ecs", ecs_access.map(drop).map_err(CronitorError::from)),
+    ]
+}
+
+/// max number of clusters a single describe_clusters request accepts
+const DESCRIBE_CLUSTERS_LIMIT: usize = 100;
+
+/// fail when any of the clusters doesn't exist or is inactive, rather than
+/// silently finding no tasks in them, naming the clusters which are
+/// available instead
+pub async fn check_clusters<E: EcsApi>(
+    ecs: &E,
+    clusters: &[String],
+    retries: &Retries,
+) -> Result<(), CronitorError> {
+    let mut invalid = Vec::new();
+    for batch in clusters.chunks(DESCRIBE_CLUSTERS_LIMIT) {
+        let request = DescribeClustersRequest {
+            clusters: Some(batch.to_vec()),
+            ..DescribeClustersRequest::default()
+        };
+        let result = retry(retries, || ecs.describe_clusters(request.clone())).await?;
+        for failure in result.failures.unwrap_or_default() {
+            invalid.push(format!(
+                "{} ({})",
+                failure
+                    .arn
+                    .as_deref()
+                    .and_then(cluster_name)
+                    .unwrap_or_default(),
+                failure.reason.as_deref().unwrap_or("MISSING")
+            ));
+        }
+        for cluster in result.clusters.unwrap_or_default() {
+            if cluster.status.as_deref() == Some("INACTIVE") {
+                invalid.push(format!(
+                    "{} (INACTIVE)",
+                    cluster.cluster_name.unwrap_or_default()
+                ));
+            }
+        }
+    }
+    if invalid.is_empty() {
+        return Ok(());
+    }
+    let mut available = Vec::new();
+    let mut next_token = None;
+    loop {
+        let request = ListClustersRequest {
+            next_token: next_token.clone(),
+            ..ListClustersRequest::default()
+        };
+        let result = retry(retries, || ecs.list_clusters(request.clone())).await?;
+        available.extend(
+            result
+                .cluster_arns
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|arn| cluster_name(arn))
+                .map(String::from),
+        );
+        next_token = result.next_token;
+        if next_token.is_none() {
+            break;
+        }
+    }
+    available.sort();
+    Err(CronitorError::NotFound(format!(
+        "invalid clusters {}, available clusters are {}",
+        invalid.join(", "),
+        if available.is_empty() {
+            "none".into()
+        } else {
+            available.join(", ")
+        }
+    )))
+}
+
+/// get the tags of a rule
+async fn rule_tags<E: EventsApi>(
+    events: &E,
+    rule: &Rule,
+    retries: &Retries,
+) -> Result<HashMap<String, String>, CronitorError> {
+    let request = ListTagsForResourceRequest {
+        resource_arn: rule.arn.clone().unwrap_or_default(),
+    };
+    debug!("listing tags of rule {}", rule.name);
+    let result = retry(retries, || events.list_tags_for_resource(request.clone())).await?;
+    Ok(result
+        .tags
+        .unwrap_or_default()
+        .into_iter()
+        .map(|tag| (tag.key, tag.value))
+        .collect())
+}
+
+/// keep only the rules whose tags satisfy every filter, querying at most
+/// `concurrency` rules at once and collecting the rules we failed to query
+/// separately
+///
+/// tags rarely change so they're looked up once per rule arn and kept in
+/// `tags` for subsequent calls
+pub async fn filter_by_tags<E: EventsApi>(
+    events: &E,
+    rules: Vec<Rule>,
+    filters: &[TagFilter],
+    tags: &Mutex<HashMap<String, HashMap<String, String>>>,
+    concurrency: usize,
+    retries: &Retries,
+    deadline: Deadline,
+) -> (Vec<Rule>, Vec<RuleError>) {
+    let results = stream::iter(rules)
+        .map(|rule| async move {
+            let key = rule.arn.clone().unwrap_or_else(|| rule.name.clone());
+            let cached = tags.lock().expect("tag cache poisoned").get(&key).cloned();
+            let rule_tags = match cached {
+                Some(rule_tags) => rule_tags,
+                _ => {
+                    let rule_tags = deadline
+                        .within(rule_tags(events, &rule, retries))
+                        .await
+                        .map_err(|error| RuleError::new(&rule, error))?;
+                    tags.lock()
+                        .expect("tag cache poisoned")
+                        .insert(key, rule_tags.clone());
+                    rule_tags
+                }
+            };
+            Ok(Some(rule).filter(|_| filters.iter().all(|filter| filter.matches(&rule_tags))))
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    let (rules, errors) = partition(results);
+    (rules.into_iter().flatten().collect(), errors)
+}
+
+/// list the arns of the task definitions launched by a rule's ecs targets,
+/// and of the lambda functions it targets, following pagination until the
+/// last page
+async fn list_targets<E: EventsApi>(
+    events: &E,
+    rule: &Rule,
+    retries: &Retries,
+) -> Result<(Vec<String>, Vec<String>), CronitorError> {
+    let mut task_definitions = Vec::new();
+    let mut lambda_functions = Vec::new();
+    let mut next_token = None;
+    loop {
+        let request = ListTargetsByRuleRequest {
+            event_bus_name: rule.event_bus_name.clone(),
+            rule: rule.name.clone(),
+            next_token,
+            ..ListTargetsByRuleRequest::default()
+        };
+        debug!("listing targets of rule {}", rule.name);
+        let result = retry(retries, || events.list_targets_by_rule(request.clone())).await?;
+        for target in result.targets.unwrap_or_default() {
+            match target.ecs_parameters {
+                Some(ecs) => task_definitions.push(ecs.task_definition_arn),
+                _ if lambda_function(&target.arn).is_some() => lambda_functions.push(target.arn),
+                _ => (),
+            }
+        }
+        match result.next_token {
+            Some(token) => next_token = Some(token),
+            _ => return Ok((task_definitions, lambda_functions)),
+        }
+    }
+}
+
+/// max number of task arns returned by a single list_tasks request
+const LIST_TASKS_LIMIT: usize = 100;
+
+/// list the arns of all tasks in a desired status started by a given rule,
+/// following pagination until the last page
+async fn list_task_arns<E: EcsApi>(
+    ecs: &E,
+    cluster: &str,
+    started_by: &str,
+    desired_status: &str,
+    limit: Option<usize>,
+    retries: &Retries,
+) -> Result<Vec<String>, CronitorError> {
+    let mut arns = Vec::new();
+    let mut next_token = None;
+    loop {
+        let request = ListTasksRequest {
+            cluster: Some(cluster.into()),
+            desired_status: Some(desired_status.into()),
+            started_by: Some(started_by.into()),
+            max_results: limit.map(|limit| (limit - arns.len()).min(LIST_TASKS_LIMIT) as i64),
+            next_token,
+            ..ListTasksRequest::default()
+        };
+        let result = retry(retries, || ecs.list_tasks(request.clone())).await?;
+        arns.extend(result.task_arns.unwrap_or_default());
+        if let Some(limit) = limit {
+            arns.truncate(limit);
+        }
+        match result.next_token {
+            Some(token) if limit.is_none_or(|limit| arns.len() < limit) => next_token = Some(token),
+            _ => {
+                debug!(
+                    "found {} {} tasks started by {}",
+                    arns.len(),
+                    desired_status,
+                    started_by
+                );
+                return Ok(arns);
+            }
+        }
+    }
+}
+
+/// describe tasks in batches of the api's max of 100 arns per request,
+/// along with the tasks which couldn't be described. no requests are made
+/// when there are no arns to describe
+async fn describe_tasks<E: EcsApi>(
+    ecs: &E,
+    cluster: &str,
+    task_arns: Vec<String>,
+    retries: &Retries,
+) -> Result<(Vec<Task>, Vec<TaskFailure>), CronitorError> {
+    let batches = try_join_all(task_arns.chunks(DESCRIBE_TASKS_LIMIT).map(|chunk| {
+        let request = DescribeTasksRequest {
+            cluster: Some(cluster.into()),
+            tasks: chunk.to_vec(),
+            ..DescribeTasksRequest::default()
+        };
+        async move {
+            debug!("describing {} tasks", request.tasks.len());
+            let result = retry(retries, || ecs.describe_tasks(request.clone())).await?;
+            dump("DescribeTasks", &result);
+            let failures = result
+                .failures
+                .unwrap_or_default()
+                .into_iter()
+                .map(|failure| TaskFailure {
+                    cluster: cluster.into(),
+                    arn: failure.arn,
+                    reason: failure.reason,
+                    detail: failure.detail,
+                })
+                .collect::<Vec<_>>();
+            for failure in &failures {
+                warn!(
+                    "failed to describe task {} in {}: {}",
+                    failure.arn.as_deref().unwrap_or_default(),
+                    cluster,
+                    failure.reason.as_deref().unwrap_or("unknown reason")
+                );
+            }
+            Ok::<_, CronitorError>((result.tasks.unwrap_or_default(), failures))
+        }
+    }))
+    .await?;
+    let (tasks, failures): (Vec<_>, Vec<_>) = batches.into_iter().unzip();
+    Ok((
+        tasks.into_iter().flatten().collect(),
+        failures.into_iter().flatten().collect(),
+    ))
+}
+
+/// cloudwatch makes no guarantees about datapoint ordering so we select
+/// the most recent timestamp rather than the positional last
+fn latest_timestamp(datapoints: Vec<Datapoint>) -> Option<String> {
+    datapoints
+        .into_iter()
+        .filter_map(|dp| dp.timestamp)
+        .filter_map(|ts| {
+            DateTime::parse_from_rfc3339(&ts)
+                .ok()
+                .map(|time| (time, ts))
+        })
+        .max_by_key(|(time, _)| *time)
+        .map(|(_, ts)| ts)
+}
+
+/// fetch the datapoints of one of a given rule's event metrics over a window,
+/// including any `statistics` beyond the query's own
+/// https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/cwe-metricscollected.html
+async fn get_rule_datapoints<M: MetricsApi>(
+    metrics: &M,
+    rule: &str,
+    metric_name: &str,
+    query: &MetricQuery,
+    statistics: &[Statistic],
+    retries: &Retries,
+) -> Result<Vec<Datapoint>, CronitorError> {
+    get_datapoints(
+        metrics,
+        &query.namespace,
+        Dimension {
+            name: query.dimension_name.clone(),
+            value: rule.into(),
+        },
+        metric_name,
+        query,
+        statistics,
+        retries,
+    )
+    .await
+}
+
+/// fetch the datapoints of a metric with a single dimension over the
+/// query's window, requesting the query's statistic and any `statistics`
+/// in the same call
+async fn get_datapoints<M: MetricsApi>(
+    metrics: &M,
+    namespace: &str,
+    dimension: Dimension,
+    metric_name: &str,
+    query: &MetricQuery,
+    statistics: &[Statistic],
+    retries: &Retries,
+) -> Result<Vec<Datapoint>, CronitorError> {
+    // always include sums so we can count events regardless of the
+    // requested statistic, and sample counts to weigh averages by
+    let mut requested = vec![query.statistic, Statistic::Sum];
+    if query.statistic == Statistic::Average {
+        requested.push(Statistic::SampleCount);
+    }
+    requested.extend(statistics);
+    let mut statistics = Vec::<String>::new();
+    for statistic in requested {
+        if !statistics.iter().any(|name| name == statistic.as_str()) {
+            statistics.push(statistic.as_str().into());
+        }
+    }
+    debug!(
+        "fetching {} metrics for {} {}",
+        metric_name, dimension.name, dimension.value
+    );
+    let mut datapoints = Vec::new();
+    for (start, end) in query.windows() {
+        let input = GetMetricStatisticsInput {
+            dimensions: Some(vec![dimension.clone()]),
+            end_time: end.to_rfc3339(),
+            metric_name: metric_name.into(),
+            namespace: namespace.into(),
+            period: query.period.num_seconds(),
+            start_time: start.to_rfc3339(),
+            statistics: Some(statistics.clone()),
+            ..GetMetricStatisticsInput::default()
+        };
+        let response = retry(retries, || metrics.get_metric_statistics(input.clone())).await?;
+        dump("GetMetricStatistics", &response);
+        datapoints.extend(response.datapoints.unwrap_or_default());
+    }
+    Ok(datapoints)
+}
+
+/// namespace of the metrics lambda publishes for each function
+const LAMBDA_NAMESPACE: &str = "AWS/Lambda";
+
+/// the name of the function a lambda target arn refers to, ignoring any
+/// alias or version qualifier, e.g. arn:aws:lambda:us-east-1:123:function:name:live
+pub fn lambda_function(arn: &str) -> Option<&str> {
+    let mut parts = arn.split(':');
+    if parts.nth(2) != Some("lambda") {
+        return None;
+    }
+    parts.nth(2).filter(|kind| *kind == "function")?;
+    parts.next().filter(|name| !name.is_empty())
+}
+
+/// count the invocations and errors of a rule's lambda functions over a
+/// window
+/// https://docs.aws.amazon.com/lambda/latest/dg/monitoring-metrics.html
+async fn lambda_invocations<M: MetricsApi>(
+    metrics: &M,
+    functions: &[String],
+    query: &MetricQuery,
+    retries: &Retries,
+) -> Result<LambdaInvocations, CronitorError> {
+    let counts = try_join_all(
+        functions
+            .iter()
+            .filter_map(|arn| lambda_function(arn))
+            .flat_map(|function| {
+                ["Invocations", "Errors"].iter().map(move |metric_name| {
+                    get_datapoints(
+                        metrics,
+                        LAMBDA_NAMESPACE,
+                        Dimension {
+                            name: "FunctionName".into(),
+                            value: function.into(),
+                        },
+                        metric_name,
+                        query,
+                        &[],
+                        retries,
+                    )
+                })
+            }),
+    )
+    .await?;
+    let mut invocations = LambdaInvocations::default();
+    for pair in counts.chunks(2) {
+        invocations.count += count_triggers(&pair[0]);
+        invocations.errors += count_triggers(&pair[1]);
+    }
+    Ok(invocations)
+}
+
+/// fetch the datapoints of a given rule's triggered events over a window
+pub async fn get_trigger_datapoints<M: MetricsApi>(
+    metrics: &M,
+    rule: &str,
+    query: &MetricQuery,
+    retries: &Retries,
+) -> Result<Vec<Datapoint>, CronitorError> {
+    get_rule_datapoints(metrics, rule, "TriggeredRules", query, &[], retries).await
+}
+
+/// fetch the datapoints of a given rule's failed target invocations over a window.
+/// these indicate a rule fired but failed to start its target
+pub async fn get_failed_invocation_datapoints<M: MetricsApi>(
+    metrics: &M,
+    rule: &str,
+    query: &MetricQuery,
+    retries: &Retries,
+) -> Result<Vec<Datapoint>, CronitorError> {
+    get_rule_datapoints(metrics, rule, "FailedInvocations", query, &[], retries).await
+}
+
+/// what a single request for a rule's trigger metrics reveals about it
+#[derive(Clone, Debug)]
+pub struct TriggerStatistics {
+    /// timestamp of the most recent trigger
+    pub last: Option<String>,
+    /// total number of triggers
+    pub count: u64,
+    /// the largest Maximum across datapoints, when requested. Cloud Watch
+    /// events publishes triggers each minute, so this is the most triggers
+    /// seen in a single minute
+    pub max_burst: Option<u64>,
+    /// the query's statistic across every datapoint
+    pub statistic: Option<f64>,
+    pub datapoints: Vec<Datapoint>,
+}
+
+/// get the timestamp of the last time a given rule triggered an event,
+/// along with whatever else `statistics` reveal, in one request per window
+pub async fn get_last_trigger<M: MetricsApi>(
+    metrics: &M,
+    rule: &str,
+    query: &MetricQuery,
+    statistics: &[Statistic],
+    retries: &Retries,
+) -> Result<TriggerStatistics, CronitorError> {
+    let datapoints =
+        get_rule_datapoints(metrics, rule, "TriggeredRules", query, statistics, retries).await?;
+    Ok(TriggerStatistics {
+        last: latest_timestamp(datapoints.clone()),
+        count: count_triggers(&datapoints),
+        max_burst: datapoints
+            .iter()
+            .filter_map(|dp| dp.maximum)
+            .fold(None, |max: Option<f64>, value| {
+                Some(max.map_or(value, |max| max.max(value)))
+            })
+            .map(|max| max.round() as u64),
+        statistic: query.statistic.across(&datapoints),
+        datapoints,
+    })
+}
+
+/// sum the number of events, e.g. triggers, across a set of datapoints
+pub fn count_triggers(datapoints: &[Datapoint]) -> u64 {
+    datapoints
+        .iter()
+        .filter_map(|dp| dp.sum)
+        .sum::<f64>()
+        .round() as u64
+}
+
+/// sum the number of events per day of a query's window, oldest first
+///
+/// datapoints are bucketed by how many days before the window's end they
+/// start, so periods longer than a day are counted on the day they start
+pub fn daily_counts(
+    datapoints: &[Datapoint],
+    query: &MetricQuery,
+) -> Vec<u64> {
+    let end = query.end.unwrap_or_else(Utc::now);
+    let days = ((query.since.num_seconds() + 86_399) / 86_400).max(1) as usize;
+    let mut counts = vec![0.0; days];
+    for datapoint in datapoints {
+        let time = match datapoint
+            .timestamp
+            .as_deref()
+            .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+        {
+            Some(time) => time,
+            _ => continue,
+        };
+        let days_ago = end.signed_duration_since(time).num_days().max(0) as usize;
+        if let Some(count) = days.checked_sub(days_ago + 1).map(|day| &mut counts[day]) {
+            *count += datapoint.sum.unwrap_or_default();
+        }
+    }
+    counts
+        .into_iter()
+        .map(|count| count.round() as u64)
+        .collect()
+}
+
+/// split per rule results into successes and errors
+fn partition<T>(results: Vec<Result<T, RuleError>>) -> (Vec<T>, Vec<RuleError>) {
+    let mut successes = Vec::new();
+    let mut errors = Vec::new();
+    for result in results {
+        match result {
+            Ok(success) => successes.push(success),
+            Err(err) => {
+                warn!("failed to query rule {}: {}", err.rule, err.error);
+                errors.push(err)
+            }
+        }
+    }
+    (successes, errors)
+}
+
+/// true when a task definition was deleted or is inactive
+///
+/// ecs answers requests for deleted task definitions with a client error
+/// rather than anything more specific, so only that error is taken to mean
+/// it's gone, while any other is the rule's to report
+async fn broken_target<C: EcsApi>(
+    ecs: &C,
+    task_definition_arn: &str,
+    retries: &Retries,
+) -> Result<bool, CronitorError> {
+    let request = DescribeTaskDefinitionRequest {
+        task_definition: task_definition_arn.into(),
+        ..DescribeTaskDefinitionRequest::default()
+    };
+    debug!("describing task definition {}", task_definition_arn);
+    let described = retry(retries, || async {
+        match ecs.describe_task_definition(request.clone()).await {
+            Err(RusotoError::Service(DescribeTaskDefinitionError::Client(message))) => {
+                debug!(
+                    "failed to describe task definition {}: {}",
+                    task_definition_arn, message
+                );
+                Ok(None)
+            }
+            result => result.map(Some),
+        }
+    })
+    .await?;
+    Ok(match described {
+        Some(response) => response
+            .task_definition
+            .and_then(|definition| definition.status)
+            .map(|status| status == "INACTIVE")
+            .unwrap_or_default(),
+        _ => true,
+    })
+}
+
+/// resolve the task definitions each rule launches, which of those are
+/// broken, and the lambda functions it targets, querying at most
+/// `concurrency` rules at once, collecting the rules we failed to query
+/// separately
+pub async fn with_targets<E: EventsApi, C: EcsApi>(
+    events: &E,
+    ecs: &C,
+    rules: Vec<Rule>,
+    concurrency: usize,
+    retries: &Retries,
+    deadline: Deadline,
+) -> (Vec<Rule>, Vec<RuleError>) {
+    let results = stream::iter(rules)
+        .map(|mut rule| async move {
+            let targets = async {
+                let (task_definitions, lambda_functions) =
+                    list_targets(events, &rule, retries).await?;
+                let broken = try_join_all(
+                    task_definitions
+                        .iter()
+                        .map(|arn| broken_target(ecs, arn, retries)),
+                )
+                .await?;
+                let broken_targets = task_definitions
+                    .iter()
+                    .zip(broken)
+                    .filter(|(_, broken)| *broken)
+                    .map(|(arn, _)| arn.clone())
+                    .collect::<Vec<_>>();
+                Ok::<_, CronitorError>((task_definitions, broken_targets, lambda_functions))
+            };
+            let (task_definitions, broken_targets, lambda_functions) = deadline
+                .within(targets)
+                .await
+                .map_err(|error| RuleError::new(&rule, error))?;
+            rule.task_definitions = task_definitions;
+            rule.broken_targets = broken_targets;
+            rule.lambda_functions = lambda_functions;
+            Ok(rule)
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    partition(results)
+}
+
+/// get the trigger history of each rule, querying at most `concurrency`
+/// rules at once, collecting the rules we failed to query separately
+#[allow(clippy::too_many_arguments)]
+pub async fn last_triggers<M: MetricsApi>(
+    metrics: &M,
+    rules: Vec<Rule>,
+    query: &MetricQuery,
+    concurrency: usize,
+    jitter: StdDuration,
+    retries: &Retries,
+    deadline: Deadline,
+    trend: bool,
+) -> (Vec<(Rule, Triggers)>, Vec<RuleError>) {
+    let previous = query.previous();
+    let previous = &previous;
+    // the minutes ecs still lists stopped tasks for
+    let retention = MetricQuery {
+        since: Duration::minutes(STOPPED_TASK_RETENTION_MINUTES),
+        period: Duration::minutes(1),
+        end: None,
+        ..query.clone()
+    };
+    let retention = &retention;
+    let results = stream::iter(rules)
+        .map(|rule| async move {
+            // spread out each rule's first requests so a burst of them
+            // doesn't trip cloudwatch's rate limits, without counting
+            // against the rule's own timeout
+            within(deadline.run, async {
+                sleep(spread(jitter)).await;
+                Ok(())
+            })
+            .await
+            .map_err(|error| RuleError::new(&rule, error))?;
+            let previous_count = async {
+                if trend {
+                    get_trigger_datapoints(metrics, &rule.name, previous, retries)
+                        .await
+                        .map(|datapoints| Some(count_triggers(&datapoints)))
+                } else {
+                    Ok(None)
+                }
+            };
+            let lambda = async {
+                if rule.lambda_functions.is_empty() {
+                    Ok(None)
+                } else {
+                    lambda_invocations(metrics, &rule.lambda_functions, query, retries)
+                        .await
+                        .map(Some)
+                }
+            };
+            let (((statistics, failures), previous_count), lambda) = deadline
+                .within(try_join(
+                    try_join(
+                        try_join(
+                            get_last_trigger(
+                                metrics,
+                                &rule.name,
+                                query,
+                                &[Statistic::Maximum],
+                                retries,
+                            ),
+                            get_failed_invocation_datapoints(metrics, &rule.name, query, retries),
+                        ),
+                        previous_count,
+                    ),
+                    lambda,
+                ))
+                .await
+                .map_err(|error| RuleError::new(&rule, error))?;
+            // buckets coarser than a minute don't tell whether the rule
+            // triggered recently enough for ecs to still list its task,
+            // so the last of them is queried again by the minute
+            let last_bucket_retained = statistics
+                .last
+                .as_deref()
+                .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+                .map(|last| last + query.period > Utc::now() - retention.since)
+                .unwrap_or_default();
+            let recent = if query.period <= retention.period {
+                statistics.last.clone()
+            } else if matches!(rule.target_type(), TargetType::Ecs | TargetType::Mixed)
+                && query.end.is_none()
+                && last_bucket_retained
+            {
+                deadline
+                    .within(get_trigger_datapoints(
+                        metrics, &rule.name, retention, retries,
+                    ))
+                    .await
+                    .map(latest_timestamp)
+                    .map_err(|error| RuleError::new(&rule, error))?
+            } else {
+                None
+            };
+            let triggers = Triggers {
+                count: statistics.count,
+                failed_invocations: count_triggers(&failures),
+                daily: daily_counts(&statistics.datapoints, query),
+                last: statistics.last,
+                max_burst: statistics.max_burst,
+                statistic: statistics.statistic,
+                recent,
+                previous_count,
+                lambda,
+            };
+            Ok((rule, triggers))
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    partition(results)
+}
+
+/// describe the tasks within a cluster started by a given `started_by` tag,
+/// along with those which couldn't be described
+async fn started_tasks<E: EcsApi>(
+    ecs: &E,
+    cluster: &str,
+    started_by: &str,
+    status: Status,
+    limit: Option<usize>,
+    retries: &Retries,
+) -> Result<(Vec<Task>, Vec<TaskFailure>), CronitorError> {
+    let listings = try_join_all(status.desired_statuses().iter().map(|desired_status| {
+        list_task_arns(ecs, cluster, started_by, desired_status, limit, retries)
+    }))
+    .await?;
+    let (tasks, failures) = describe_tasks(
+        ecs,
+        cluster,
+        listings.into_iter().flatten().collect(),
+        retries,
+    )
+    .await?;
+    Ok((most_recent(tasks, limit), failures))
+}
+
+/// the `limit` most recently stopped tasks, counting tasks which haven't
+/// stopped as the most recent, or all tasks when there's no limit
+///
+/// ecs lists tasks newest first but doesn't document it, so tasks are
+/// sorted here to be safe
+fn most_recent(
+    mut tasks: Vec<Task>,
+    limit: Option<usize>,
+) -> Vec<Task> {
+    if let Some(limit) = limit {
+        tasks.sort_by(|a, b| {
+            let stopped = |task: &Task| task.stopped_at.unwrap_or(f64::INFINITY);
+            stopped(b).total_cmp(&stopped(a))
+        });
+        tasks.truncate(limit);
+    }
+    tasks
+}
+
+/// max length of the `startedBy` tag ecs stores on tasks
+const STARTED_BY_LIMIT: usize = 36;
+
+/// the `startedBy` tag template Cloud Watch events uses for the tasks a
+/// rule starts, where `{rule}` is replaced by the rule's name
+pub const DEFAULT_STARTED_BY: &str = "events-rule/{rule}";
+
+/// the `startedBy` tag of the tasks a rule starts, given a template like
+/// `DEFAULT_STARTED_BY`
+///
+/// ecs truncates `startedBy` to 36 characters, so by default rules whose
+/// names share their first 24 characters get the same tag and their tasks
+/// can't be told apart, see `started_by_collisions`
+pub fn started_by(
+    template: &str,
+    rule: &str,
+) -> String {
+    template
+        .replace("{rule}", rule)
+        .chars()
+        .take(STARTED_BY_LIMIT)
+        .collect()
+}
+
+/// groups of rule names which share a `startedBy` tag, and so whose tasks
+/// may be attributed to one another
+pub fn started_by_collisions(
+    rules: &[Rule],
+    template: &str,
+) -> Vec<(String, Vec<String>)> {
+    let mut by_started_by = BTreeMap::<String, Vec<String>>::new();
+    for rule in rules {
+        by_started_by
+            .entry(started_by(template, &rule.name))
+            .or_default()
+            .push(rule.name.clone());
+    }
+    by_started_by
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .collect()
+}
+
+/// a rule, its triggers, and the tasks it started
+pub type RuleResult = (Rule, Triggers, Vec<Task>);
+
+/// get the tasks each rule has started across clusters, or only its `limit`
+/// most recent, querying at most `concurrency` rules at once, collecting the
+/// rules we failed to query separately
+///
+/// `on_result` is called with each rule's result as soon as it's gathered
+#[allow(clippy::too_many_arguments)]
+pub async fn stopped_tasks_for_rules<E: EcsApi>(
+    ecs: &E,
+    clusters: &[String],
+    started_by_template: &str,
+    triggers: Vec<(Rule, Triggers)>,
+    status: Status,
+    limit: Option<usize>,
+    concurrency: usize,
+    retries: &Retries,
+    deadline: Deadline,
+    on_result: &(dyn Fn(&RuleResult) + Sync),
+) -> (Vec<RuleResult>, Vec<RuleError>) {
+    let results = stream::iter(triggers)
+        .map(|(mut rule, triggers)| async move {
+            // lambda rules never start tasks, so there's nothing to look up
+            if rule.target_type() == TargetType::Lambda {
+                let result = (rule, triggers, Vec::new());
+                on_result(&result);
+                return Ok(result);
+            }
+            let started_by = started_by(started_by_template, &rule.name);
+            let (tasks, failures): (Vec<_>, Vec<_>) = deadline
+                .within(try_join_all(clusters.iter().map(|cluster| {
+                    started_tasks(ecs, cluster, &started_by, status, limit, retries)
+                })))
+                .await
+                .map_err(|error| RuleError::new(&rule, error))?
+                .into_iter()
+                .unzip();
+            let tasks = most_recent(tasks.into_iter().flatten().collect(), limit);
+            rule.task_failures = failures.into_iter().flatten().collect();
+            // a recent trigger's task may still be running, and so not be
+            // among the stopped tasks listed
+            if matches!(status, Status::Stopped)
+                && tasks.is_empty()
+                && triggered_within_retention(triggers.recent_trigger(), status, Utc::now())
+            {
+                rule.running_tasks = deadline
+                    .within(try_join_all(clusters.iter().map(|cluster| {
+                        started_tasks(ecs, cluster, &started_by, Status::Running, limit, retries)
+                    })))
+                    .await
+                    .map_err(|error| RuleError::new(&rule, error))?
+                    .into_iter()
+                    .flat_map(|(tasks, _)| tasks)
+                    .filter_map(|task| task.task_arn)
+                    .collect();
+            }
+            info!(
+                "found {} tasks for rule {} across {} clusters",
+                tasks.len(),
+                rule.name,
+                clusters.len()
+            );
+            let result = (rule, triggers, tasks);
+            on_result(&result);
+            Ok(result)
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    partition(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusoto_ecs::{
+        DescribeClustersError, DescribeClustersResponse, DescribeTaskDefinitionError,
+        DescribeTaskDefinitionResponse, DescribeTasksError, DescribeTasksResponse,
+        ListClustersError, ListClustersResponse, ListTasksError, ListTasksResponse,
+    };
+    use rusoto_events::{
+        DescribeRuleError, DescribeRuleResponse, ListRulesError, ListRulesResponse,
+        ListTagsForResourceError, ListTagsForResourceResponse, ListTargetsByRuleError,
+        ListTargetsByRuleResponse, Rule as EventRule,
+    };
+
+    /// serves each page of rules in turn, handing out the next page's index
+    /// as its token
+    struct PagedRules {
+        pages: Vec<Vec<&'static str>>,
+    }
+
+    #[async_trait]
+    impl EventsApi for PagedRules {
+        async fn list_rules(
+            &self,
+            input: ListRulesRequest,
+        ) -> Result<ListRulesResponse, RusotoError<ListRulesError>> {
+            let page = input
+                .next_token
+                .map(|token| token.parse::<usize>().expect("invalid token"))
+                .unwrap_or_default();
+            Ok(ListRulesResponse {
+                rules: Some(
+                    self.pages[page]
+                        .iter()
+                        .map(|name| EventRule {
+                            name: Some(name.to_string()),
+                            state: Some("ENABLED".into()),
+                            ..EventRule::default()
+                        })
+                        .collect(),
+                ),
+                next_token: Some(page + 1)
+                    .filter(|next| *next < self.pages.len())
+                    .map(|next| next.to_string()),
+            })
+        }
+
+        async fn describe_rule(
+            &self,
+            _: DescribeRuleRequest,
+        ) -> Result<DescribeRuleResponse, RusotoError<DescribeRuleError>> {
+            unimplemented!()
+        }
+
+        async fn list_targets_by_rule(
+            &self,
+            _: ListTargetsByRuleRequest,
+        ) -> Result<ListTargetsByRuleResponse, RusotoError<ListTargetsByRuleError>> {
+            unimplemented!()
+        }
+
+        async fn list_tags_for_resource(
+            &self,
+            _: ListTagsForResourceRequest,
+        ) -> Result<ListTagsForResourceResponse, RusotoError<ListTagsForResourceError>> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn list_matching_rules_follows_pagination() {
+        let events = PagedRules {
+            pages: vec![vec!["nightly-a", "nightly-b"], vec!["nightly-c"]],
+        };
+        let rules = list_matching_rules(&events, None, "nightly", RuleState::Enabled)
+            .await
+            .expect("failed to list rules");
+        assert_eq!(
+            rules
+                .iter()
+                .map(|rule| rule.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["nightly-a", "nightly-b", "nightly-c"]
+        );
+    }
+
+    /// serves each page of task arns in turn, like `PagedRules`
+    struct PagedTasks {
+        pages: Vec<Vec<&'static str>>,
+    }
+
+    #[async_trait]
+    impl EcsApi for PagedTasks {
+        async fn list_tasks(
+            &self,
+            input: ListTasksRequest,
+        ) -> Result<ListTasksResponse, RusotoError<ListTasksError>> {
+            let page = input
+                .next_token
+                .map(|token| token.parse::<usize>().expect("invalid token"))
+                .unwrap_or_default();
+            Ok(ListTasksResponse {
+                task_arns: Some(self.pages[page].iter().map(|arn| arn.to_string()).collect()),
+                next_token: Some(page + 1)
+                    .filter(|next| *next < self.pages.len())
+                    .map(|next| next.to_string()),
+            })
+        }
+
+        async fn describe_tasks(
+            &self,
+            _: DescribeTasksRequest,
+        ) -> Result<DescribeTasksResponse, RusotoError<DescribeTasksError>> {
+            unimplemented!()
+        }
+
+        async fn describe_task_definition(
+            &self,
+            _: DescribeTaskDefinitionRequest,
+        ) -> Result<DescribeTaskDefinitionResponse, RusotoError<DescribeTaskDefinitionError>>
+        {
+            unimplemented!()
+        }
+
+        async fn describe_clusters(
+            &self,
+            _: DescribeClustersRequest,
+        ) -> Result<DescribeClustersResponse, RusotoError<DescribeClustersError>> {
+            unimplemented!()
+        }
+
+        async fn list_clusters(
+            &self,
+            _: ListClustersRequest,
+        ) -> Result<ListClustersResponse, RusotoError<ListClustersError>> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn list_task_arns_follows_pagination() {
+        let ecs = PagedTasks {
+            pages: vec![vec!["task/a", "task/b"], vec!["task/c"]],
+        };
+        let retries = Retries::throttled(0);
+        let arns = list_task_arns(
+            &ecs,
+            "default",
+            "events-rule/nightly",
+            "STOPPED",
+            None,
+            &retries,
+        )
+        .await
+        .expect("failed to list tasks");
+        assert_eq!(arns, vec!["task/a", "task/b", "task/c"]);
+    }
+
+    #[tokio::test]
+    async fn list_task_arns_stops_paginating_at_limit() {
+        let ecs = PagedTasks {
+            pages: vec![vec!["task/a", "task/b"], vec!["task/c"]],
+        };
+        let retries = Retries::throttled(0);
+        let arns = list_task_arns(
+            &ecs,
+            "default",
+            "events-rule/nightly",
+            "STOPPED",
+            Some(2),
+            &retries,
+        )
+        .await
+        .expect("failed to list tasks");
+        assert_eq!(arns, vec!["task/a", "task/b"]);
+    }
+
+    #[test]
+    fn latest_timestamp_ignores_datapoint_order() {
+        let datapoints = [
+            "2020-01-02T00:00:00Z",
+            "2020-01-03T00:00:00Z",
+            "2020-01-01T00:00:00Z",
+        ]
+        .iter()
+        .map(|ts| Datapoint {
+            timestamp: Some(ts.to_string()),
+            sum: Some(1.0),
+            ..Datapoint::default()
+        })
+        .collect();
+        assert_eq!(
+            latest_timestamp(datapoints),
+            Some("2020-01-03T00:00:00Z".into())
+        );
+    }
+
+    fn daily_query() -> MetricQuery {
+        MetricQuery {
+            since: Duration::days(7),
+            period: Duration::days(1),
+            statistic: Statistic::Sum,
+            namespace: DEFAULT_NAMESPACE.into(),
+            dimension_name: DEFAULT_DIMENSION_NAME.into(),
+            end: None,
+        }
+    }
+
+    #[test]
+    fn is_stale_measures_age_from_the_end_of_the_last_bucket() {
+        let query = daily_query();
+        let now = Utc.with_ymd_and_hms(2020, 1, 3, 12, 0, 0).unwrap();
+        // the bucket of 2020-01-02 closed 12 hours ago
+        assert!(!is_stale(
+            Some("2020-01-02T00:00:00Z"),
+            &query,
+            Duration::days(1),
+            now
+        ));
+        // a bucket which closed exactly a day ago
+        assert!(!is_stale(
+            Some("2020-01-01T12:00:00Z"),
+            &query,
+            Duration::days(1),
+            now
+        ));
+        assert!(is_stale(
+            Some("2020-01-01T11:59:59Z"),
+            &query,
+            Duration::days(1),
+            now
+        ));
+        // the bucket of 2020-01-03 is still open
+        assert!(!is_stale(
+            Some("2020-01-03T00:00:00Z"),
+            &query,
+            Duration::zero(),
+            now
+        ));
+        assert!(is_stale(None, &query, Duration::days(1), now));
+    }
+
+    #[test]
+    fn statistic_across_weights_averages_by_sample_count() {
+        let datapoints = [(2.0, 1.0, 1.0, 1.0), (6.0, 2.0, 3.0, 3.0)]
+            .iter()
+            .map(|(sum, sample_count, minimum, maximum)| Datapoint {
+                sum: Some(*sum),
+                sample_count: Some(*sample_count),
+                minimum: Some(*minimum),
+                maximum: Some(*maximum),
+                ..Datapoint::default()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(Statistic::Sum.across(&datapoints), Some(8.0));
+        assert_eq!(Statistic::SampleCount.across(&datapoints), Some(3.0));
+        assert_eq!(Statistic::Minimum.across(&datapoints), Some(1.0));
+        assert_eq!(Statistic::Maximum.across(&datapoints), Some(3.0));
+        assert_eq!(Statistic::Average.across(&datapoints), Some(8.0 / 3.0));
+        assert_eq!(Statistic::Maximum.across(&[]), None);
+        assert_eq!(Statistic::Average.across(&[]), None);
+    }
+
+    /// fails every task definition lookup with an error of a given kind
+    struct FailingTaskDefinitions(fn(String) -> DescribeTaskDefinitionError);
+
+    #[async_trait]
+    impl EcsApi for FailingTaskDefinitions {
+        async fn list_tasks(
+            &self,
+            _: ListTasksRequest,
+        ) -> Result<ListTasksResponse, RusotoError<ListTasksError>> {
+            unimplemented!()
+        }
+
+        async fn describe_tasks(
+            &self,
+            _: DescribeTasksRequest,
+        ) -> Result<DescribeTasksResponse, RusotoError<DescribeTasksError>> {
+            unimplemented!()
+        }
+
+        async fn describe_task_definition(
+            &self,
+            _: DescribeTaskDefinitionRequest,
+        ) -> Result<DescribeTaskDefinitionResponse, RusotoError<DescribeTaskDefinitionError>>
+        {
+            Err(RusotoError::Service((self.0)(
+                "Unable to describe task definition.".into(),
+            )))
+        }
+
+        async fn describe_clusters(
+            &self,
+            _: DescribeClustersRequest,
+        ) -> Result<DescribeClustersResponse, RusotoError<DescribeClustersError>> {
+            unimplemented!()
+        }
+
+        async fn list_clusters(
+            &self,
+            _: ListClustersRequest,
+        ) -> Result<ListClustersResponse, RusotoError<ListClustersError>> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn broken_target_only_on_client_errors() {
+        let retries = Retries::throttled(0);
+        let arn = "arn:aws:ecs:us-east-1:123:task-definition/nightly:1";
+        let missing = FailingTaskDefinitions(DescribeTaskDefinitionError::Client);
+        assert!(broken_target(&missing, arn, &retries)
+            .await
+            .expect("client errors mean the task definition is gone"));
+        let unavailable = FailingTaskDefinitions(DescribeTaskDefinitionError::Server);
+        assert!(broken_target(&unavailable, arn, &retries).await.is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! retrieval of the logs of failed containers
+//!
+//! only containers logging with the awslogs driver and a stream prefix can
+//! be located, see
+//! https://docs.aws.amazon.com/AmazonECS/latest/developerguide/using_awslogs.html
+
+use crate::{api::EcsApi, retry, Retries, RuleReport};
+use futures::stream::{self, StreamExt};
+use log::{debug, warn};
+use rusoto_ecs::{DescribeTaskDefinitionRequest, TaskDefinition};
+use rusoto_logs::{CloudWatchLogs, GetLogEventsRequest};
+use std::collections::{BTreeSet, HashMap};
+
+/// where a container's logs are written
+struct LogStream {
+    group: String,
+    stream: String,
+}
+
+/// locate the log stream of a task's container from its task definition
+fn log_stream(
+    definition: &TaskDefinition,
+    container: &str,
+    task_arn: &str,
+) -> Option<LogStream> {
+    let config = definition
+        .container_definitions
+        .iter()
+        .flatten()
+        .find(|definition| definition.name.as_deref() == Some(container))?
+        .log_configuration
+        .as_ref()
+        .filter(|config| config.log_driver == "awslogs")?;
+    let options = config.options.as_ref()?;
+    let task_id = task_arn.rsplit('/').next()?;
+    Some(LogStream {
+        group: options.get("awslogs-group")?.clone(),
+        stream: format!(
+            "{}/{}/{}",
+            options.get("awslogs-stream-prefix")?,
+            container,
+            task_id
+        ),
+    })
+}
+
+/// fetch the last `lines` log events of each container which exited with a
+/// non-zero exit code, querying at most `concurrency` streams at once.
+/// logs we fail to locate or fetch are skipped rather than failing the report
+pub async fn tail_failed_containers<E: EcsApi, L: CloudWatchLogs>(
+    ecs: &E,
+    logs: &L,
+    reports: &mut [RuleReport],
+    lines: usize,
+    concurrency: usize,
+    retries: &Retries,
+) {
+    let arns = reports
+        .iter()
+        .flat_map(|report| &report.stopped_tasks)
+        .filter(|task| task.failed())
+        .filter_map(|task| task.task_definition_arn.clone())
+        .collect::<BTreeSet<_>>();
+    let definitions = stream::iter(arns)
+        .map(|arn| async move {
+            let request = DescribeTaskDefinitionRequest {
+                task_definition: arn.clone(),
+                ..DescribeTaskDefinitionRequest::default()
+            };
+            let result = retry(retries, || ecs.describe_task_definition(request.clone())).await;
+            (arn, result)
+        })
+        .buffer_unordered(concurrency)
+        .filter_map(|(arn, result)| async move {
+            match result {
+                Ok(response) => response.task_definition.map(|definition| (arn, definition)),
+                Err(err) => {
+                    warn!("failed to describe task definition {}: {}", arn, err);
+                    None
+                }
+            }
+        })
+        .collect::<HashMap<_, _>>()
+        .await;
+
+    let mut streams = Vec::new();
+    for (r, report) in reports.iter().enumerate() {
+        for (t, task) in report.stopped_tasks.iter().enumerate() {
+            let (definition, task_arn) = match (&task.task_definition_arn, &task.task_arn) {
+                (Some(definition), Some(task_arn)) => match definitions.get(definition) {
+                    Some(definition) => (definition, task_arn),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            for (c, container) in task.containers.iter().enumerate() {
+                if !container.failed() {
+                    continue;
+                }
+                let located = container
+                    .name
+                    .as_deref()
+                    .and_then(|name| log_stream(definition, name, task_arn));
+                match located {
+                    Some(located) => streams.push(((r, t, c), located)),
+                    _ => debug!("no awslogs stream found for a container of {}", task_arn),
+                }
+            }
+        }
+    }
+
+    let fetched = stream::iter(streams)
+        .map(|(position, located)| async move {
+            let request = GetLogEventsRequest {
+                log_group_name: located.group.clone(),
+                log_stream_name: located.stream.clone(),
+                limit: Some(lines as i64),
+                start_from_head: Some(false),
+                ..GetLogEventsRequest::default()
+            };
+            let result = retry(retries, || logs.get_log_events(request.clone())).await;
+            (position, located, result)
+        })
+        .buffer_unordered(concurrency)
+        .collect::<Vec<_>>()
+        .await;
+    for ((r, t, c), located, result) in fetched {
+        match result {
+            Ok(response) => {
+                reports[r].stopped_tasks[t].containers[c].logs = response
+                    .events
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|event| event.message)
+                    .collect()
+            }
+            Err(err) => warn!(
+                "failed to fetch logs from {} {}: {}",
+                located.group, located.stream, err
+            ),
+        }
+    }
+}
@@ -1,132 +1,3784 @@
 use chrono::{prelude::*, Duration};
-use futures::{future::join_all, Future};
-use rusoto_cloudwatch::{CloudWatch, CloudWatchClient, Dimension, GetMetricStatisticsInput};
-use rusoto_core::{credential::ChainProvider, request::HttpClient};
-use rusoto_ecs::{DescribeTasksRequest, Ecs, EcsClient, ListTasksRequest};
-use rusoto_events::{CloudWatchEvents, CloudWatchEventsClient, ListRulesRequest};
-use std::time::Duration as StdDuration;
-use structopt::StructOpt;
-use tokio::runtime::Runtime;
+use cronitor::{
+    account_id,
+    api::{EcsApi, EventsApi, MetricsApi},
+    archive::{self, S3Uri},
+    assume_role,
+    cache::Cache,
+    check_clusters, config, credentials, describe_rules, dump_responses, filter_by_tags,
+    group_by_family, interrupt, lambda_function, last_triggers, list_matching_rules,
+    logs::tail_failed_containers,
+    notify,
+    relative::{ago_rfc3339, span, until_rfc3339},
+    started_by_collisions, stopped_tasks_for_rules, task_family, validate_access, with_targets,
+    within, CronitorError, Deadline, Dispatcher, MetricQuery, Report, Retries, Rule, RuleError,
+    RuleReport, RuleResult, RuleState, Statistic, Status, StoppedTask, TagFilter, TargetType,
+    Timezone, Triggers, DEFAULT_DIMENSION_NAME, DEFAULT_NAMESPACE, DEFAULT_STARTED_BY,
+};
+use hyper::{
+    header::{ACCEPT, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{error, warn};
+use regex::Regex;
+use rusoto_cloudwatch::CloudWatchClient;
+use rusoto_core::Region;
+use rusoto_ecs::{EcsClient, Task};
+use rusoto_events::EventBridgeClient;
+use rusoto_logs::CloudWatchLogsClient;
+use rusoto_s3::S3Client;
+use rusoto_sns::SnsClient;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap, HashSet},
+    convert::Infallible,
+    ffi::OsString,
+    io::IsTerminal,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration as StdDuration,
+};
+use structopt::{clap::Shell, StructOpt};
+use tokio::{sync::Mutex, time::Instant};
 
 #[derive(StructOpt)]
-#[structopt(name = "cronitor", about = "tool for introspecting AWS ECS crons")]
+#[structopt(
+    name = "cronitor",
+    about = "tool for introspecting AWS ECS crons",
+    after_help = "EXIT CODES:\n    0    success\n    1    invalid options, failed to query AWS for any rule, or failed to write output\n    2    unhealthy rules found by check or list --fail-on-stale\n    3    timed out before querying every rule, see --timeout and --rule-timeout\n    130  interrupted, after reporting the rules queried so far\n\nlist -o nagios instead exits 0, 1, or 2 for ok, warning, or critical rules, or 3 when AWS couldn't be queried",
+    // lets completions run without --rule and --cluster, which main enforces
+    // for every other subcommand
+    raw(setting = "structopt::clap::AppSettings::SubcommandsNegateReqs")
+)]
 struct Options {
+    #[structopt(
+        long = "config",
+        parse(from_os_str),
+        help = "toml file of defaults for global options, keyed by their long names, defaults to ./cronitor.toml or ~/.config/cronitor/cronitor.toml"
+    )]
+    config: Option<PathBuf>,
     #[structopt(
         short = "r",
         long = "rule",
-        help = "name of Cloud Watch event rule or rule prefix"
+        raw(required_unless = r#""rules_from""#, number_of_values = "1"),
+        help = "name of Cloud Watch event rule or rule prefix, may be repeated"
+    )]
+    prefixes: Vec<String>,
+    #[structopt(
+        long = "rules-from",
+        parse(from_os_str),
+        raw(conflicts_with = r#""prefixes""#),
+        help = "file of newline separated exact rule names to check rather than matching --rule prefixes, or - to read stdin"
+    )]
+    rules_from: Option<PathBuf>,
+    #[structopt(
+        long = "bus",
+        help = "name or arn of the event bus to list rules on, defaults to the default bus"
+    )]
+    bus: Option<String>,
+    #[structopt(
+        long = "tag",
+        raw(number_of_values = "1"),
+        help = "only include rules tagged key=value, may be repeated to require every tag"
+    )]
+    tags: Vec<TagFilter>,
+    #[structopt(
+        short = "c",
+        long = "cluster",
+        raw(required = "true", number_of_values = "1"),
+        help = "ECS cluster name, may be repeated"
+    )]
+    clusters: Vec<String>,
+    #[structopt(
+        long = "started-by-prefix",
+        raw(default_value = "DEFAULT_STARTED_BY"),
+        parse(try_from_str = "parse_started_by"),
+        help = "template of the startedBy tag rules' tasks are listed by, where {rule} is the rule's name, truncated to 36 characters as ecs does"
+    )]
+    started_by: String,
+    #[structopt(
+        short = "s",
+        long = "since",
+        default_value = "1w",
+        parse(try_from_str = "parse_duration"),
+        help = "how far back to look for rule triggers, e.g. 12h, 30d, 2w"
+    )]
+    since: Duration,
+    #[structopt(
+        long = "start",
+        parse(try_from_str = "parse_timestamp"),
+        help = "start of a fixed window to look for rule triggers in, as an RFC3339 timestamp, overriding --since"
+    )]
+    start: Option<DateTime<Utc>>,
+    #[structopt(
+        long = "end",
+        parse(try_from_str = "parse_timestamp"),
+        help = "end of the window to look for rule triggers in, as an RFC3339 timestamp, defaulting to now"
+    )]
+    end: Option<DateTime<Utc>>,
+    #[structopt(
+        long = "region",
+        parse(try_from_str = "parse_region"),
+        help = "AWS region, defaults to the region resolved from the environment"
+    )]
+    region: Option<Region>,
+    #[structopt(
+        short = "p",
+        long = "profile",
+        help = "named AWS profile from your shared credentials file"
+    )]
+    profile: Option<String>,
+    #[structopt(
+        long = "status",
+        default_value = "stopped",
+        help = "desired status of tasks to list, one of stopped, running, or all"
+    )]
+    status: Status,
+    #[structopt(
+        long = "task-limit",
+        parse(try_from_str = "parse_task_limit"),
+        help = "only fetch each rule's n most recent tasks, speeding up scans of busy clusters"
+    )]
+    task_limit: Option<usize>,
+    #[structopt(
+        long = "overlap-check",
+        help = "also list running tasks, flagging rules with more than one running at once"
+    )]
+    overlap_check: bool,
+    #[structopt(
+        long = "trend",
+        help = "compare each rule's trigger count against the preceding window of the same length"
+    )]
+    trend: bool,
+    #[structopt(
+        long = "debug",
+        help = "print raw AWS rule, metric, and task responses to stderr, regardless of RUST_LOG"
+    )]
+    debug: bool,
+    #[structopt(
+        long = "validate",
+        help = "make one lightweight call against each AWS api to check access, then exit"
+    )]
+    validate: bool,
+    #[structopt(
+        long = "concurrency",
+        default_value = "8",
+        parse(try_from_str = "parse_concurrency"),
+        help = "max number of rules to query AWS for at once"
+    )]
+    concurrency: usize,
+    #[structopt(
+        long = "jitter",
+        default_value = "250",
+        help = "max milliseconds to randomly delay each rule's metric queries by, spreading out bursts of requests"
+    )]
+    jitter: u64,
+    #[structopt(
+        long = "max-retries",
+        default_value = "5",
+        help = "max number of times to retry a throttled AWS request, or one failing with --retry-on"
+    )]
+    max_retries: usize,
+    #[structopt(
+        long = "retry-on",
+        raw(use_delimiter = "true"),
+        help = "comma separated AWS error codes to retry, defaults to throttling codes, e.g. Throttling,RequestLimitExceeded"
+    )]
+    retry_on: Vec<String>,
+    #[structopt(
+        long = "max-age",
+        parse(try_from_str = "parse_duration"),
+        help = "flag rules as stale when not triggered within this duration, e.g. 1d, measured from the end of the --period bucket of their last trigger"
+    )]
+    max_age: Option<Duration>,
+    #[structopt(
+        long = "timezone",
+        default_value = "utc",
+        help = "timezone used to display timestamps, one of local, utc, or a name like America/New_York"
+    )]
+    timezone: Timezone,
+    #[structopt(
+        long = "credential-timeout",
+        default_value = "200",
+        help = "milliseconds to wait on each credential provider, e.g. instance metadata"
+    )]
+    credential_timeout: u64,
+    #[structopt(
+        long = "imds",
+        raw(conflicts_with = r#""profile""#),
+        help = "fetch credentials only from instance metadata, using the token based IMDSv2 flow, or from the ecs container credentials endpoint when AWS_CONTAINER_CREDENTIALS_RELATIVE_URI is set"
+    )]
+    imds: bool,
+    #[structopt(
+        long = "metadata-timeout",
+        default_value = "1000",
+        help = "milliseconds to wait on each instance or container metadata request made with --imds"
+    )]
+    metadata_timeout: u64,
+    #[structopt(
+        long = "connect-timeout",
+        parse(try_from_str = "parse_duration"),
+        help = "how long to wait connecting to AWS, e.g. 5s"
+    )]
+    connect_timeout: Option<Duration>,
+    #[structopt(
+        long = "read-timeout",
+        parse(try_from_str = "parse_duration"),
+        help = "how long to wait on each AWS response, e.g. 30s"
+    )]
+    read_timeout: Option<Duration>,
+    #[structopt(
+        long = "timeout",
+        parse(try_from_str = "parse_duration"),
+        help = "give up on rules not yet queried after this long, e.g. 2m, reporting the rest"
+    )]
+    timeout: Option<Duration>,
+    #[structopt(
+        long = "rule-timeout",
+        parse(try_from_str = "parse_duration"),
+        help = "report a rule as timed out when its calls to any one api take longer than this, e.g. 10s"
+    )]
+    rule_timeout: Option<Duration>,
+    #[structopt(
+        long = "assume-role-arn",
+        help = "IAM role to assume with the resolved credentials, e.g. for cross-account access"
+    )]
+    assume_role_arn: Option<String>,
+    #[structopt(
+        long = "external-id",
+        raw(requires = r#""assume_role_arn""#),
+        help = "external id required by the trust policy of --assume-role-arn"
+    )]
+    external_id: Option<String>,
+    #[structopt(
+        long = "period",
+        default_value = "1d",
+        parse(try_from_str = "parse_duration"),
+        help = "width of each trigger metric datapoint, e.g. 1h. when wider than 1m, rules targeting ecs whose last datapoint overlaps the past hour have it queried again by the minute to tell whether they triggered without starting a task"
+    )]
+    period: Duration,
+    #[structopt(
+        long = "statistic",
+        default_value = "Sum",
+        help = "trigger metric statistic reported across the window as trigger_statistic, one of SampleCount, Average, Sum, Minimum, or Maximum"
+    )]
+    statistic: Statistic,
+    #[structopt(
+        long = "namespace",
+        raw(default_value = "DEFAULT_NAMESPACE"),
+        help = "cloudwatch namespace to query rule metrics from"
+    )]
+    namespace: String,
+    #[structopt(
+        long = "dimension-name",
+        raw(default_value = "DEFAULT_DIMENSION_NAME"),
+        help = "cloudwatch metric dimension whose value is the rule name"
+    )]
+    dimension_name: String,
+    #[structopt(
+        long = "state",
+        default_value = "enabled",
+        help = "state of rules to include, one of enabled, disabled, or all"
+    )]
+    state: RuleState,
+    #[structopt(
+        long = "exact",
+        help = "only match rules named exactly as given by --rule rather than by prefix"
+    )]
+    exact: bool,
+    #[structopt(
+        long = "family",
+        help = "only include tasks from this task definition family"
+    )]
+    family: Option<String>,
+    #[structopt(
+        long = "reason-filter",
+        parse(try_from_str = "Regex::new"),
+        help = "only include tasks whose stopped reason matches this regex"
+    )]
+    reason_filter: Option<Regex>,
+    #[structopt(
+        long = "container",
+        help = "only report the exit code and reason of the container with this name, e.g. to ignore sidecars"
+    )]
+    container: Option<String>,
+    #[structopt(
+        long = "slack-webhook",
+        raw(requires = r#""max_age""#),
+        help = "slack incoming webhook url to notify when list or check finds stale rules"
+    )]
+    slack_webhook: Option<String>,
+    #[structopt(
+        long = "sns-topic-arn",
+        help = "sns topic to publish the json report to when list or check finds stale or failing rules"
     )]
-    rule: String,
-    #[structopt(short = "c", long = "cluster", help = "ECS cluster name")]
-    cluster: String,
+    sns_topic_arn: Option<String>,
+    #[structopt(
+        long = "always-notify",
+        raw(requires = r#""sns_topic_arn""#),
+        help = "publish to --sns-topic-arn even when every rule is healthy"
+    )]
+    always_notify: bool,
+    #[structopt(
+        long = "pagerduty-routing-key",
+        help = "pagerduty events api v2 routing key to trigger an incident with for each stale or failing rule"
+    )]
+    pagerduty_routing_key: Option<String>,
+    #[structopt(
+        long = "pagerduty-resolve",
+        raw(requires = r#""pagerduty_routing_key""#),
+        help = "resolve the pagerduty incidents of rules which are healthy again"
+    )]
+    pagerduty_resolve: bool,
+    #[structopt(
+        long = "emit-metrics",
+        help = "publish each rule's seconds since its last trigger as a CronLastTriggerAgeSeconds metric in the Cronitor namespace"
+    )]
+    emit_metrics: bool,
+    #[structopt(
+        long = "cache",
+        parse(from_os_str),
+        help = "directory to cache query results in, reusing them on runs within --cache-ttl"
+    )]
+    cache_dir: Option<PathBuf>,
+    #[structopt(
+        long = "cache-ttl",
+        default_value = "5m",
+        parse(try_from_str = "parse_duration"),
+        help = "how long results cached with --cache are reused for"
+    )]
+    cache_ttl: Duration,
+    #[structopt(
+        long = "refresh",
+        raw(requires = r#""cache_dir""#),
+        help = "ignore results cached with --cache, replacing them with fresh ones"
+    )]
+    refresh: bool,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    #[structopt(
+        name = "list",
+        about = "list rules along with their recent triggers and tasks"
+    )]
+    List {
+        #[structopt(
+            short = "o",
+            long = "output",
+            default_value = "debug",
+            help = "output format, one of csv, debug, json, jsonl, markdown, nagios, table, or yaml"
+        )]
+        output: Output,
+        #[structopt(
+            long = "color",
+            default_value = "auto",
+            help = "when to color table output, one of auto, always, or never"
+        )]
+        color: Color,
+        #[structopt(
+            long = "only-stale",
+            help = "only output rules that never triggered or are older than --max-age"
+        )]
+        only_stale: bool,
+        #[structopt(
+            long = "quiet",
+            short = "q",
+            help = "only output unhealthy rules, and nothing at all when every rule is healthy"
+        )]
+        quiet: bool,
+        #[structopt(
+            long = "min-invocations",
+            help = "only output rules which triggered at least this many times within --since"
+        )]
+        min_invocations: Option<u64>,
+        #[structopt(
+            long = "max-invocations",
+            help = "only output rules which triggered at most this many times within --since, e.g. to find under-running crons"
+        )]
+        max_invocations: Option<u64>,
+        #[structopt(
+            long = "watch",
+            parse(try_from_str = "parse_duration"),
+            raw(conflicts_with = r#""fail_on_stale""#),
+            help = "re-run on this interval until interrupted, e.g. 30s"
+        )]
+        watch: Option<Duration>,
+        #[structopt(
+            long = "output-file",
+            parse(from_os_str),
+            help = "path to write output to rather than stdout, replacing any existing file"
+        )]
+        output_file: Option<PathBuf>,
+        #[structopt(
+            long = "s3-uri",
+            help = "also upload output to s3 under this prefix with a timestamped key, e.g. s3://bucket/cronitor"
+        )]
+        s3_uri: Option<S3Uri>,
+        #[structopt(
+            long = "tail",
+            parse(try_from_str = "parse_tail"),
+            help = "number of log lines to fetch for each container which exited with a non-zero exit code"
+        )]
+        tail: Option<usize>,
+        #[structopt(
+            long = "fields",
+            help = "comma separated rule fields to output in csv, json, jsonl, markdown, table, or yaml output, e.g. rule,last_trigger,stale"
+        )]
+        fields: Option<Fields>,
+        #[structopt(
+            long = "sort-by",
+            help = "order rules by one of last-trigger (stalest first), name, or stopped-count (most first)"
+        )]
+        sort_by: Option<SortBy>,
+        #[structopt(
+            long = "group-by",
+            help = "roll up table, markdown, json, or yaml output by family, the task definition family of each rule, with per-family totals"
+        )]
+        group_by: Option<GroupBy>,
+        #[structopt(
+            long = "reverse",
+            raw(requires = r#""sort_by""#),
+            help = "reverse the order given by --sort-by"
+        )]
+        reverse: bool,
+        #[structopt(
+            long = "sparkline",
+            help = "render each rule's daily triggers over the window as a sparkline in debug and table output"
+        )]
+        sparkline: bool,
+        #[structopt(
+            long = "fail-on-stale",
+            help = "exit with a non-zero status when any rule is stale, requires --max-age"
+        )]
+        fail_on_stale: bool,
+    },
+    #[structopt(
+        name = "check",
+        about = "report unhealthy rules, exiting with a non-zero status when any are found"
+    )]
+    Check,
+    #[structopt(
+        name = "serve",
+        about = "serve rule health as prometheus or openmetrics metrics over http"
+    )]
+    Serve {
+        #[structopt(
+            long = "addr",
+            default_value = "0.0.0.0:9090",
+            help = "address to listen on"
+        )]
+        addr: SocketAddr,
+        #[structopt(
+            long = "cache",
+            parse(try_from_str = "parse_duration"),
+            help = "reuse metrics gathered within this duration rather than querying AWS on every scrape, e.g. 5m"
+        )]
+        cache: Option<Duration>,
+    },
+    #[structopt(name = "export", about = "export the json report to a file")]
+    Export {
+        #[structopt(
+            short = "f",
+            long = "file",
+            parse(from_os_str),
+            help = "path to write the report to, defaults to stdout"
+        )]
+        file: Option<PathBuf>,
+    },
+    #[structopt(
+        name = "completions",
+        about = "print a shell completion script to stdout",
+        raw(setting = "structopt::clap::AppSettings::Hidden")
+    )]
+    Completions {
+        #[structopt(
+            raw(possible_values = "&Shell::variants()", case_insensitive = "true"),
+            help = "shell to generate completions for"
+        )]
+        shell: Shell,
+    },
 }
 
-fn credentials() -> ChainProvider {
-    let mut chain = ChainProvider::new();
-    chain.set_timeout(StdDuration::from_millis(200));
-    chain
+/// process exit code used when we fail to query AWS
+const EXIT_AWS_ERROR: i32 = 1;
+
+/// process exit code used for invalid combinations of options, matching
+/// the exit code used for argument parsing errors
+const EXIT_USAGE: i32 = 1;
+
+/// process exit code used when we fail to write output
+const EXIT_IO_ERROR: i32 = 1;
+
+/// process exit code used when unhealthy rules are found by check or
+/// list --fail-on-stale
+const EXIT_UNHEALTHY: i32 = 2;
+
+/// process exit code used when a run doesn't complete within --timeout, or
+/// a rule exceeds --rule-timeout
+const EXIT_TIMEOUT: i32 = 3;
+
+/// process exit code used when a run is interrupted by SIGINT, as shells
+/// report for processes killed by it
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// exit for a run which failed outright, distinguishing timeouts and
+/// interruptions
+fn run_failed(err: CronitorError) -> ! {
+    let code = if err.interrupted() {
+        EXIT_INTERRUPTED
+    } else if err.timed_out() {
+        EXIT_TIMEOUT
+    } else {
+        EXIT_AWS_ERROR
+    };
+    fail(err, code)
 }
 
-/// get the timestamp of the last time a given rule triggered an event
-/// https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/cwe-metricscollected.html
-fn get_last_trigger(
-    metrics: std::sync::Arc<CloudWatchClient>,
-    rule: &str,
-    since: Duration,
-) -> impl Future<Item = Option<String>, Error = String> {
-    let now = Utc::now();
-    let start = now - since;
-    metrics
-        .get_metric_statistics(GetMetricStatisticsInput {
-            dimensions: Some(vec![Dimension {
-                name: "RuleName".into(),
-                value: rule.into(),
-            }]),
-            end_time: now.to_rfc3339(),
-            metric_name: "TriggeredRules".into(),
-            namespace: "AWS/Events".into(),
-            period: Duration::days(1).num_seconds(),
-            start_time: start.to_rfc3339(),
-            statistics: Some(vec!["Sum".into()]),
-            ..GetMetricStatisticsInput::default()
+/// like `run_failed`, reported as a nagios plugin's UNKNOWN verdict when
+/// `nagios` is set, as it's neither a warning nor critical
+fn query_failed(
+    err: CronitorError,
+    nagios: bool,
+) -> ! {
+    if nagios {
+        println!("UNKNOWN: {}", err);
+        std::process::exit(NAGIOS_UNKNOWN);
+    }
+    run_failed(err)
+}
+
+/// on the first SIGINT abandon the rules not yet queried, so those which
+/// were are still reported, exiting straight away on a second
+fn interrupt_on_ctrl_c() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("interrupted, reporting the rules queried so far");
+            interrupt();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(EXIT_INTERRUPTED);
+            }
+        }
+    });
+}
+
+/// the exit code for a report with rules we failed to query, if any
+fn incomplete(report: &Report) -> Option<i32> {
+    if report.errors.iter().any(|err| err.interrupted) {
+        Some(EXIT_INTERRUPTED)
+    } else if report.errors.iter().any(|err| err.timed_out) {
+        Some(EXIT_TIMEOUT)
+    } else if !report.errors.is_empty() {
+        Some(EXIT_AWS_ERROR)
+    } else {
+        None
+    }
+}
+
+/// nagios plugin exit code for healthy rules
+const NAGIOS_OK: i32 = 0;
+
+/// nagios plugin exit code for degraded rules
+const NAGIOS_WARNING: i32 = 1;
+
+/// nagios plugin exit code for stale rules or rules with broken targets
+const NAGIOS_CRITICAL: i32 = 2;
+
+/// nagios plugin exit code for rules we failed to query, or when AWS
+/// couldn't be queried at all
+const NAGIOS_UNKNOWN: i32 = 3;
+
+/// a single line nagios plugin verdict with perfdata, and its exit code
+///
+/// stale rules and rules with broken targets are critical and rules which
+/// are otherwise unhealthy are a warning, while rules we failed to query are
+/// unknown unless there's something worse to report
+fn nagios(report: &Report) -> (String, i32) {
+    let problem = |names: &[&str], problem: &str| {
+        format!("{} crons {} ({})", names.len(), problem, names.join(", "))
+    };
+    let stale = report
+        .rules
+        .iter()
+        .filter(|rule| rule.stale)
+        .map(|rule| rule.rule.as_str())
+        .collect::<Vec<_>>();
+    let broken = report
+        .rules
+        .iter()
+        .filter(|rule| !rule.broken_targets.is_empty())
+        .map(|rule| rule.rule.as_str())
+        .collect::<Vec<_>>();
+    let degraded = report
+        .rules
+        .iter()
+        .filter(|rule| !rule.stale && rule.broken_targets.is_empty() && unhealthy(rule))
+        .map(|rule| rule.rule.as_str())
+        .collect::<Vec<_>>();
+    let failed = report
+        .errors
+        .iter()
+        .map(|err| err.rule.as_str())
+        .collect::<Vec<_>>();
+    let perfdata = format!(
+        "rules={} stale={} broken={} degraded={} errors={}",
+        report.summary.rules,
+        stale.len(),
+        broken.len(),
+        degraded.len(),
+        failed.len()
+    );
+    let mut problems = Vec::new();
+    if !stale.is_empty() {
+        problems.push(problem(&stale, "stale"));
+    }
+    if !broken.is_empty() {
+        problems.push(problem(&broken, "with broken targets"));
+    }
+    if !degraded.is_empty() {
+        problems.push(problem(&degraded, "degraded"));
+    }
+    if !failed.is_empty() {
+        problems.push(problem(&failed, "failed to query"));
+    }
+    let (verdict, code) = if !stale.is_empty() || !broken.is_empty() {
+        ("CRITICAL", NAGIOS_CRITICAL)
+    } else if !degraded.is_empty() {
+        ("WARNING", NAGIOS_WARNING)
+    } else if !failed.is_empty() {
+        ("UNKNOWN", NAGIOS_UNKNOWN)
+    } else {
+        problems.push(format!("{} crons healthy", report.summary.rules));
+        ("OK", NAGIOS_OK)
+    };
+    (
+        format!("{}: {} | {}", verdict, problems.join(", "), perfdata),
+        code,
+    )
+}
+
+/// how to order listed rules
+#[derive(Clone, Copy)]
+enum SortBy {
+    LastTrigger,
+    Name,
+    StoppedCount,
+}
+
+impl SortBy {
+    /// compare rules so the most suspicious sort first, where rules which
+    /// never triggered are infinitely stale
+    fn compare(
+        self,
+        a: &RuleReport,
+        b: &RuleReport,
+    ) -> Ordering {
+        let last_trigger = |report: &RuleReport| {
+            report
+                .last_trigger
+                .as_deref()
+                .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+        };
+        match self {
+            SortBy::LastTrigger => last_trigger(a).cmp(&last_trigger(b)),
+            SortBy::Name => a.rule.cmp(&b.rule),
+            SortBy::StoppedCount => b.stopped_tasks.len().cmp(&a.stopped_tasks.len()),
+        }
+    }
+}
+
+/// how to roll up listed rules
+#[derive(Clone, Copy)]
+enum GroupBy {
+    /// by the task definition family each rule launches
+    Family,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "family" => Ok(GroupBy::Family),
+            _ => Err(format!("invalid grouping '{}': expected family", value)),
+        }
+    }
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "last-trigger" => Ok(SortBy::LastTrigger),
+            "name" => Ok(SortBy::Name),
+            "stopped-count" => Ok(SortBy::StoppedCount),
+            _ => Err(format!(
+                "invalid sort '{}': expected one of last-trigger, name, or stopped-count",
+                value
+            )),
+        }
+    }
+}
+
+/// when to emit ANSI colors
+#[derive(Clone, Copy)]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    /// auto only colors output written to a terminal so piped or
+    /// redirected output stays clean
+    fn enabled(self) -> bool {
+        match self {
+            Color::Auto => std::io::stdout().is_terminal(),
+            Color::Always => true,
+            Color::Never => false,
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(Color::Auto),
+            "always" => Ok(Color::Always),
+            "never" => Ok(Color::Never),
+            _ => Err(format!(
+                "invalid color '{}': expected one of auto, always, or never",
+                value
+            )),
+        }
+    }
+}
+
+/// supported output formats
+enum Output {
+    Csv,
+    Debug,
+    Json,
+    /// one json object per rule, streamed as each rule's results are
+    /// gathered
+    Jsonl,
+    /// a github flavored markdown table
+    Markdown,
+    /// a single line verdict exiting with nagios plugin codes
+    Nagios,
+    Table,
+    Yaml,
+}
+
+impl Output {
+    /// media type of the rendered output, e.g. for uploads
+    fn content_type(&self) -> &'static str {
+        match self {
+            Output::Csv => "text/csv",
+            Output::Json => "application/json",
+            Output::Jsonl => "application/x-ndjson",
+            Output::Markdown => "text/markdown",
+            Output::Yaml => "application/yaml",
+            Output::Debug | Output::Nagios | Output::Table => "text/plain",
+        }
+    }
+
+    /// file extension of the rendered output
+    fn extension(&self) -> &'static str {
+        match self {
+            Output::Csv => "csv",
+            Output::Json => "json",
+            Output::Jsonl => "jsonl",
+            Output::Markdown => "md",
+            Output::Yaml => "yaml",
+            Output::Debug | Output::Nagios | Output::Table => "txt",
+        }
+    }
+}
+
+impl FromStr for Output {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "csv" => Ok(Output::Csv),
+            "debug" => Ok(Output::Debug),
+            "json" => Ok(Output::Json),
+            "jsonl" => Ok(Output::Jsonl),
+            "markdown" => Ok(Output::Markdown),
+            "nagios" => Ok(Output::Nagios),
+            "table" => Ok(Output::Table),
+            "yaml" => Ok(Output::Yaml),
+            _ => Err(format!(
+                "invalid output '{}': expected one of csv, debug, json, jsonl, markdown, nagios, table, or yaml",
+                value
+            )),
+        }
+    }
+}
+
+fn parse_concurrency(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "invalid concurrency '{}': expected a positive number",
+            value
+        )),
+    }
+}
+
+fn parse_task_limit(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "invalid task limit '{}': expected a positive number",
+            value
+        )),
+    }
+}
+
+fn parse_tail(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "invalid number of log lines '{}': expected a positive number",
+            value
+        )),
+    }
+}
+
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| {
+            format!(
+                "invalid timestamp '{}': expected RFC3339 like 2019-01-01T00:00:00Z: {}",
+                value, e
+            )
         })
-        .map_err(|e| e.to_string())
-        .map(|response| {
-            response
-                .datapoints
-                .unwrap_or_default()
+}
+
+fn parse_started_by(value: &str) -> Result<String, String> {
+    if value.contains("{rule}") {
+        Ok(value.into())
+    } else {
+        Err(format!(
+            "invalid started by prefix '{}': expected a template containing {{rule}}",
+            value
+        ))
+    }
+}
+
+fn parse_region(value: &str) -> Result<Region, String> {
+    value
+        .parse::<Region>()
+        .map_err(|_| format!("unknown AWS region '{}', e.g. us-east-1", value))
+}
+
+/// parse a human friendly duration, a count followed by a unit
+/// of s(econds), m(inutes), h(ours), d(ays), or w(eeks)
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (count, unit) = value.split_at(split);
+    let count = count
+        .parse::<i64>()
+        .map_err(|_| format!("invalid duration '{}': expected a count like 12h", value))?;
+    let duration = match unit {
+        "s" => Duration::try_seconds(count),
+        "m" => Duration::try_minutes(count),
+        "h" => Duration::try_hours(count),
+        "d" => Duration::try_days(count),
+        "w" => Duration::try_weeks(count),
+        _ => {
+            return Err(format!(
+                "invalid duration '{}': unit must be one of s, m, h, d, or w",
+                value
+            ))
+        }
+    };
+    // durations are measured back from now, which must stay representable
+    duration
+        .filter(|duration| Utc::now().checked_sub_signed(*duration).is_some())
+        .ok_or_else(|| format!("invalid duration '{}': too long", value))
+}
+
+/// longest rule name rendered in table output before truncating
+const MAX_RULE_WIDTH: usize = 48;
+
+/// format a number of seconds in its largest whole unit, e.g. "3h", or
+/// in seconds when under a minute
+fn seconds(seconds: f64) -> String {
+    span(Duration::seconds(seconds as i64)).unwrap_or_else(|| format!("{:.0}s", seconds))
+}
+
+/// render a statistic without a fractional part when it has none, e.g. a sum
+fn statistic(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+fn truncate(
+    value: &str,
+    width: usize,
+) -> String {
+    if value.chars().count() <= width {
+        value.into()
+    } else {
+        let mut truncated = value.chars().take(width - 1).collect::<String>();
+        truncated.push('…');
+        truncated
+    }
+}
+
+/// fields of each rule report which may be selected with --fields
+const FIELDS: &[&str] = &[
+    "rule",
+    "arn",
+    "state",
+    "task_definitions",
+    "broken_targets",
+    "target_type",
+    "lambda_functions",
+    "last_trigger",
+    "trigger_count",
+    "max_burst",
+    "trigger_statistic",
+    "daily_triggers",
+    "failed_invocations",
+    "lambda_invocations",
+    "lambda_errors",
+    "success_rate",
+    "trend",
+    "stale",
+    "schedule",
+    "next_run",
+    "overdue",
+    "overlapping_tasks",
+    "triggered_without_task",
+    "console_url",
+    "average_duration_seconds",
+    "exit_codes",
+    "stopped_tasks",
+    "task_failures",
+];
+
+/// the rule report fields to output
+struct Fields(Vec<String>);
+
+impl Fields {
+    fn all() -> Self {
+        Fields(FIELDS.iter().map(|field| field.to_string()).collect())
+    }
+
+    fn includes(
+        &self,
+        field: &str,
+    ) -> bool {
+        self.0.iter().any(|included| included == field)
+    }
+
+    /// serialize a rule report, keeping only the selected fields
+    fn select_rule(
+        &self,
+        report: &RuleReport,
+    ) -> serde_json::Value {
+        let mut value = serde_json::to_value(report).expect("failed to serialize output");
+        if let Some(rule) = value.as_object_mut() {
+            rule.retain(|field, _| self.includes(field));
+        }
+        value
+    }
+
+    /// serialize a report, keeping only the selected fields of each rule,
+    /// with its rules nested in groups when grouped
+    fn select(
+        &self,
+        report: &Report,
+        group_by: Option<GroupBy>,
+    ) -> serde_json::Value {
+        let select_rules = |value: &mut serde_json::Value| {
+            for rule in value
+                .get_mut("rules")
+                .and_then(|rules| rules.as_array_mut())
                 .into_iter()
-                .last()
-                .and_then(move |dp| dp.timestamp)
+                .flatten()
+                .filter_map(|rule| rule.as_object_mut())
+            {
+                rule.retain(|field, _| self.includes(field));
+            }
+        };
+        match group_by {
+            Some(GroupBy::Family) => {
+                let groups = group_by_family(&report.rules)
+                    .iter()
+                    .map(|group| {
+                        let mut value =
+                            serde_json::to_value(group).expect("failed to serialize output");
+                        select_rules(&mut value);
+                        value
+                    })
+                    .collect::<Vec<_>>();
+                json!({
+                    "groups": groups,
+                    "errors": report.errors,
+                    "summary": report.summary,
+                })
+            }
+            _ => {
+                let mut value = serde_json::to_value(report).expect("failed to serialize output");
+                select_rules(&mut value);
+                value
+            }
+        }
+    }
+}
+
+impl FromStr for Fields {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let fields = value
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(|field| {
+                if FIELDS.contains(&field) {
+                    Ok(field.to_string())
+                } else {
+                    Err(format!(
+                        "unknown field '{}': expected any of {}",
+                        field,
+                        FIELDS.join(", ")
+                    ))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if fields.is_empty() {
+            return Err("expected at least one field".into());
+        }
+        Ok(Fields(fields))
+    }
+}
+
+/// how a table column's values are aligned
+#[derive(Clone, Copy)]
+enum Align {
+    Left,
+    Right,
+}
+
+/// a single table row, optionally highlighted
+struct Row {
+    cells: Vec<String>,
+    red: bool,
+}
+
+/// render rows as aligned columns under a header, highlighting rows
+/// in red when color is enabled
+fn columns(
+    header: &[(&str, Align)],
+    rows: Vec<Row>,
+    color: bool,
+) -> String {
+    let widths = header
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| {
+            rows.iter()
+                .map(|row| row.cells[i].chars().count())
+                .chain(Some(name.len()))
+                .max()
+                .unwrap_or_default()
         })
+        .collect::<Vec<_>>();
+    let header_row = Row {
+        cells: header.iter().map(|(name, _)| name.to_string()).collect(),
+        red: false,
+    };
+    let mut out = String::new();
+    for row in Some(header_row).into_iter().chain(rows) {
+        let line = row
+            .cells
+            .iter()
+            .zip(header)
+            .zip(&widths)
+            .map(|((cell, (_, align)), width)| match align {
+                Align::Left => format!("{:<width$}", cell, width = width),
+                Align::Right => format!("{:>width$}", cell, width = width),
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        if row.red && color {
+            out.push_str(&format!("\x1b[31m{}\x1b[0m", line.trim_end()));
+        } else {
+            out.push_str(line.trim_end());
+        }
+        out.push('\n');
+    }
+    out
 }
 
-fn main() {
-    let Options { rule, cluster } = Options::from_args();
-    let mut rt = Runtime::new().expect("failed to create runtime");
-    let creds = credentials();
+/// render one aligned row per rule, highlighting stale rules and rules
+/// with failed invocations or tasks
+fn table(
+    reports: &[RuleReport],
+    color: bool,
+    fields: &Fields,
+    sparkline: bool,
+    group_by: Option<GroupBy>,
+) -> String {
+    let (header, rows) = match group_by {
+        Some(GroupBy::Family) => family_rows(reports, fields, sparkline, true),
+        _ => table_rows(reports, fields, sparkline, true),
+    };
+    columns(&header, rows, color)
+}
 
-    let events = CloudWatchEventsClient::new_with(
-        HttpClient::new().expect("failed to create request dispatcher"),
-        creds.clone(),
-        Default::default(),
-    );
-    let metrics = CloudWatchClient::new_with(
-        HttpClient::new().expect("failed to create request dispatcher"),
-        creds.clone(),
-        Default::default(),
-    );
-    let ecs = EcsClient::new_with(
-        HttpClient::new().expect("failed to create request dispatcher"),
-        creds,
-        Default::default(),
+/// render one github flavored markdown table row per rule, with the same
+/// columns as the table output
+fn markdown(
+    report: &Report,
+    fields: &Fields,
+    sparkline: bool,
+    group_by: Option<GroupBy>,
+) -> String {
+    let escape = |cell: &str| cell.replace('|', "\\|").replace('\n', " ");
+    let (header, rows) = match group_by {
+        Some(GroupBy::Family) => family_rows(&report.rules, fields, sparkline, false),
+        _ => table_rows(&report.rules, fields, sparkline, false),
+    };
+    let mut out = format!(
+        "| {} |\n| {} |\n",
+        header
+            .iter()
+            .map(|(name, _)| escape(name))
+            .collect::<Vec<_>>()
+            .join(" | "),
+        header
+            .iter()
+            .map(|(_, align)| match align {
+                Align::Left => "---",
+                Align::Right => "---:",
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
     );
+    for row in rows {
+        out.push_str(&format!(
+            "| {} |\n",
+            row.cells
+                .iter()
+                .map(|cell| escape(cell))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        ));
+    }
+    if !report.errors.is_empty() {
+        out.push_str("\n**errors**\n\n");
+        for err in &report.errors {
+            out.push_str(&format!("- `{}`: {}\n", err.rule, escape(&err.error)));
+        }
+    }
+    out.push_str(&format!("\n{}\n", report.summary));
+    out
+}
 
-    let rules = events
-        .list_rules(ListRulesRequest {
-            name_prefix: Some(rule.clone()),
-            ..ListRulesRequest::default()
+/// table rows grouped by task definition family, each group led by a row
+/// of its totals with its rules indented beneath, leaving rules of unknown
+/// family on their own
+fn family_rows(
+    reports: &[RuleReport],
+    fields: &Fields,
+    sparkline: bool,
+    truncate_rules: bool,
+) -> (Vec<(&'static str, Align)>, Vec<Row>) {
+    let (header, _) = table_rows(&[], fields, sparkline, truncate_rules);
+    let rule_column = header.iter().position(|(name, _)| *name == "RULE");
+    let mut rows = Vec::new();
+    for group in group_by_family(reports) {
+        let (_, mut rule_rows) = table_rows(
+            group.rules.iter().copied(),
+            fields,
+            sparkline,
+            truncate_rules,
+        );
+        if let Some(family) = &group.family {
+            rows.push(Row {
+                cells: header
+                    .iter()
+                    .map(|(name, _)| match *name {
+                        "RULE" => family.clone(),
+                        "TRIGGERS" => group.trigger_count.to_string(),
+                        "FAILED INVOCATIONS" => group.failed_invocations.to_string(),
+                        "FAILED TASKS" => group.failed_tasks.to_string(),
+                        "STATUS" if group.stale > 0 => format!("{} STALE", group.stale),
+                        _ => String::new(),
+                    })
+                    .collect(),
+                red: rule_rows.iter().any(|row| row.red),
+            });
+            if let Some(column) = rule_column {
+                for row in &mut rule_rows {
+                    row.cells[column] = format!("  {}", row.cells[column]);
+                }
+            }
+        }
+        rows.extend(rule_rows);
+    }
+    (header, rows)
+}
+
+/// the header and rows shared by table and markdown output, keeping only
+/// the columns derived from selected fields
+fn table_rows<'a>(
+    reports: impl IntoIterator<Item = &'a RuleReport>,
+    fields: &Fields,
+    sparkline: bool,
+    truncate_rules: bool,
+) -> (Vec<(&'static str, Align)>, Vec<Row>) {
+    let rows: Vec<Row> = reports
+        .into_iter()
+        .map(|report| {
+            let failed = report
+                .stopped_tasks
+                .iter()
+                .filter(|task| task.failed())
+                .count();
+            let lambda = report.target_type == TargetType::Lambda;
+            Row {
+                cells: vec![
+                    if truncate_rules {
+                        truncate(&report.rule, MAX_RULE_WIDTH)
+                    } else {
+                        report.rule.clone()
+                    },
+                    report.state.clone().unwrap_or_default(),
+                    report.target_type.as_str().into(),
+                    if report.task_definitions.is_empty() && report.lambda_functions.is_empty() {
+                        "none".into()
+                    } else {
+                        report
+                            .task_definitions
+                            .iter()
+                            .map(|arn| arn.rsplit('/').next().unwrap_or(arn))
+                            .chain(
+                                report
+                                    .lambda_functions
+                                    .iter()
+                                    .filter_map(|arn| lambda_function(arn)),
+                            )
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    },
+                    report
+                        .stopped_tasks
+                        .iter()
+                        .filter_map(|task| task.cluster.as_deref())
+                        .collect::<BTreeSet<_>>()
+                        .into_iter()
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    report
+                        .last_trigger
+                        .as_ref()
+                        .map(|ts| ago_rfc3339(ts))
+                        .unwrap_or_else(|| "never".into()),
+                    report
+                        .next_run
+                        .as_ref()
+                        .map(|ts| until_rfc3339(ts))
+                        .unwrap_or_default(),
+                    report.trigger_count.to_string(),
+                    report
+                        .max_burst
+                        .map(|burst| burst.to_string())
+                        .unwrap_or_default(),
+                    report.trigger_statistic.map(statistic).unwrap_or_default(),
+                    sparkline_of(&report.daily_triggers),
+                    report.failed_invocations.to_string(),
+                    // lambda rules never start tasks, so counting none is misleading
+                    if lambda {
+                        String::new()
+                    } else {
+                        report.stopped_tasks.len().to_string()
+                    },
+                    if lambda {
+                        String::new()
+                    } else {
+                        failed.to_string()
+                    },
+                    report
+                        .lambda_invocations
+                        .map(|count| count.to_string())
+                        .unwrap_or_default(),
+                    report
+                        .lambda_errors
+                        .map(|count| count.to_string())
+                        .unwrap_or_default(),
+                    report
+                        .success_rate
+                        .map(|rate| format!("{:.0}%", rate))
+                        .unwrap_or_else(|| "N/A".into()),
+                    report
+                        .trend
+                        .as_ref()
+                        .map(|trend| match trend.change_percent {
+                            Some(change) => format!("{:+} ({:+.0}%)", trend.delta, change),
+                            _ => format!("{:+}", trend.delta),
+                        })
+                        .unwrap_or_default(),
+                    report
+                        .average_duration_seconds
+                        .map(seconds)
+                        .unwrap_or_default(),
+                    report
+                        .exit_codes
+                        .iter()
+                        .map(|(code, count)| format!("{}: {}", code, count))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    [
+                        (!report.broken_targets.is_empty(), "BROKEN TARGET"),
+                        (report.stale, "STALE"),
+                        (report.overdue, "OVERDUE"),
+                        (!report.overlapping_tasks.is_empty(), "OVERLAPPING"),
+                        (report.triggered_without_task, "NO TASK"),
+                        (
+                            report.stopped_tasks.iter().any(|task| task.out_of_memory),
+                            "OOM",
+                        ),
+                        (
+                            report
+                                .stopped_tasks
+                                .iter()
+                                .any(|task| task.spot_interrupted),
+                            "SPOT INTERRUPTED",
+                        ),
+                    ]
+                    .iter()
+                    .filter(|(flagged, _)| *flagged)
+                    .map(|(_, status)| *status)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                    report.console_url.clone(),
+                ],
+                red: unhealthy(report),
+            }
         })
-        .map_err(|e| e.to_string())
-        .map(|result| {
-            result
-                .rules
-                .unwrap_or_default()
+        .collect();
+    let keep = TABLE_COLUMNS
+        .iter()
+        .map(|(_, _, sources)| {
+            sources.iter().any(|field| {
+                // sparklines are opt in as they're as wide as the window is long
+                fields.includes(field) && (sparkline || *field != "daily_triggers")
+            })
+        })
+        .collect::<Vec<_>>();
+    let rows = rows
+        .into_iter()
+        .map(|row: Row| Row {
+            cells: row
+                .cells
                 .into_iter()
-                .map(|rule| rule.name.unwrap_or_default())
+                .zip(&keep)
+                .filter(|(_, keep)| **keep)
+                .map(|(cell, _)| cell)
+                .collect(),
+            red: row.red,
+        })
+        .collect();
+    let header = TABLE_COLUMNS
+        .iter()
+        .zip(&keep)
+        .filter(|(_, keep)| **keep)
+        .map(|((name, align, _), _)| (*name, *align))
+        .collect();
+    (header, rows)
+}
+
+/// a single task, or a rule without tasks, flattened into one csv row
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    rule: &'a str,
+    arn: Option<&'a str>,
+    state: Option<&'a str>,
+    /// each target's task definition arn, separated by ;
+    task_definitions: String,
+    /// each deleted or inactive task definition arn, separated by ;
+    broken_targets: String,
+    target_type: &'a str,
+    /// each targeted lambda function arn, separated by ;
+    lambda_functions: String,
+    schedule: Option<&'a str>,
+    last_trigger: Option<&'a str>,
+    next_run: Option<&'a str>,
+    trigger_count: u64,
+    max_burst: Option<u64>,
+    trigger_statistic: Option<f64>,
+    /// triggers on each day of the window, oldest first, separated by ;
+    daily_triggers: String,
+    failed_invocations: u64,
+    success_rate: Option<f64>,
+    previous_trigger_count: Option<u64>,
+    trigger_change_percent: Option<f64>,
+    stale: bool,
+    overdue: bool,
+    /// arns of concurrently running tasks, separated by ;
+    overlapping_tasks: String,
+    triggered_without_task: bool,
+    console_url: &'a str,
+    average_duration_seconds: Option<f64>,
+    /// number of containers which exited with each code, e.g. 0=40;1=3
+    exit_code_counts: String,
+    /// each task which couldn't be described and why, e.g. arn=MISSING
+    task_failures: String,
+    task_arn: Option<&'a str>,
+    cluster: Option<&'a str>,
+    last_status: Option<&'a str>,
+    stopped_reason: Option<&'a str>,
+    started_at: Option<&'a str>,
+    stopped_at: Option<&'a str>,
+    duration_seconds: Option<f64>,
+    cpu: Option<&'a str>,
+    memory: Option<&'a str>,
+    out_of_memory: Option<bool>,
+    launch_type: Option<&'a str>,
+    capacity_provider: Option<&'a str>,
+    spot_interrupted: Option<bool>,
+    /// each container's exit code, e.g. app=1;sidecar=0
+    exit_codes: Option<String>,
+    task_console_url: Option<&'a str>,
+}
+
+impl<'a> CsvRow<'a> {
+    fn new(
+        report: &'a RuleReport,
+        task: Option<&'a StoppedTask>,
+    ) -> Self {
+        CsvRow {
+            rule: &report.rule,
+            arn: report.arn.as_deref(),
+            state: report.state.as_deref(),
+            task_definitions: report.task_definitions.join(";"),
+            broken_targets: report.broken_targets.join(";"),
+            target_type: report.target_type.as_str(),
+            lambda_functions: report.lambda_functions.join(";"),
+            schedule: report.schedule.as_deref(),
+            last_trigger: report.last_trigger.as_deref(),
+            next_run: report.next_run.as_deref(),
+            trigger_count: report.trigger_count,
+            max_burst: report.max_burst,
+            trigger_statistic: report.trigger_statistic,
+            daily_triggers: report
+                .daily_triggers
+                .iter()
+                .map(|count| count.to_string())
                 .collect::<Vec<_>>()
+                .join(";"),
+            failed_invocations: report.failed_invocations,
+            success_rate: report.success_rate,
+            previous_trigger_count: report.trend.as_ref().map(|trend| trend.previous_count),
+            trigger_change_percent: report.trend.as_ref().and_then(|trend| trend.change_percent),
+            stale: report.stale,
+            overdue: report.overdue,
+            overlapping_tasks: report.overlapping_tasks.join(";"),
+            triggered_without_task: report.triggered_without_task,
+            console_url: &report.console_url,
+            average_duration_seconds: report.average_duration_seconds,
+            exit_code_counts: report
+                .exit_codes
+                .iter()
+                .map(|(code, count)| format!("{}={}", code, count))
+                .collect::<Vec<_>>()
+                .join(";"),
+            task_failures: report
+                .task_failures
+                .iter()
+                .map(|failure| {
+                    format!(
+                        "{}={}",
+                        failure.arn.as_deref().unwrap_or_default(),
+                        failure.reason.as_deref().unwrap_or_default()
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(";"),
+            task_arn: task.and_then(|task| task.task_arn.as_deref()),
+            cluster: task.and_then(|task| task.cluster.as_deref()),
+            last_status: task.and_then(|task| task.last_status.as_deref()),
+            stopped_reason: task.and_then(|task| task.stopped_reason.as_deref()),
+            started_at: task.and_then(|task| task.started_at.as_deref()),
+            stopped_at: task.and_then(|task| task.stopped_at.as_deref()),
+            duration_seconds: task.and_then(|task| task.duration_seconds),
+            cpu: task.and_then(|task| task.cpu.as_deref()),
+            memory: task.and_then(|task| task.memory.as_deref()),
+            out_of_memory: task.map(|task| task.out_of_memory),
+            launch_type: task.and_then(|task| task.launch_type.as_deref()),
+            capacity_provider: task.and_then(|task| task.capacity_provider.as_deref()),
+            spot_interrupted: task.map(|task| task.spot_interrupted),
+            exit_codes: task.map(|task| {
+                task.containers
+                    .iter()
+                    .map(|container| {
+                        format!(
+                            "{}={}",
+                            container.name.as_deref().unwrap_or_default(),
+                            container
+                                .exit_code
+                                .map(|code| code.to_string())
+                                .unwrap_or_default()
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(";")
+            }),
+            task_console_url: task.and_then(|task| task.console_url.as_deref()),
+        }
+    }
+}
+
+/// render reports as csv with one row per task, repeating rule fields
+fn csv(
+    reports: &[RuleReport],
+    fields: &Fields,
+) -> String {
+    let columns = CSV_COLUMNS
+        .iter()
+        .filter(|(_, field)| fields.includes(field))
+        .map(|(column, _)| *column)
+        .collect::<Vec<_>>();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(&columns)
+        .expect("failed to serialize output");
+    for report in reports {
+        // rules are only flattened into one row per task when tasks are selected
+        let rows = if report.stopped_tasks.is_empty() || !fields.includes("stopped_tasks") {
+            vec![CsvRow::new(report, None)]
+        } else {
+            report
+                .stopped_tasks
+                .iter()
+                .map(|task| CsvRow::new(report, Some(task)))
+                .collect()
+        };
+        for row in rows {
+            let row = serde_json::to_value(row).expect("failed to serialize output");
+            writer
+                .write_record(columns.iter().map(|column| match &row[column] {
+                    serde_json::Value::Null => String::new(),
+                    serde_json::Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                }))
+                .expect("failed to serialize output");
+        }
+    }
+    String::from_utf8(writer.into_inner().expect("failed to serialize output"))
+        .expect("csv output is not utf8")
+}
+
+/// table columns, their alignment, and the fields they're derived from
+const TABLE_COLUMNS: &[(&str, Align, &[&str])] = &[
+    ("RULE", Align::Left, &["rule"]),
+    ("STATE", Align::Left, &["state"]),
+    ("TYPE", Align::Left, &["target_type"]),
+    (
+        "TARGET",
+        Align::Left,
+        &["task_definitions", "lambda_functions"],
+    ),
+    ("CLUSTERS", Align::Left, &["stopped_tasks"]),
+    ("LAST TRIGGER", Align::Left, &["last_trigger"]),
+    ("NEXT RUN", Align::Left, &["next_run"]),
+    ("TRIGGERS", Align::Right, &["trigger_count"]),
+    ("MAX BURST", Align::Right, &["max_burst"]),
+    ("STATISTIC", Align::Right, &["trigger_statistic"]),
+    ("DAILY", Align::Left, &["daily_triggers"]),
+    ("FAILED INVOCATIONS", Align::Right, &["failed_invocations"]),
+    ("TASKS", Align::Right, &["stopped_tasks"]),
+    ("FAILED TASKS", Align::Right, &["stopped_tasks"]),
+    ("INVOCATIONS", Align::Right, &["lambda_invocations"]),
+    ("LAMBDA ERRORS", Align::Right, &["lambda_errors"]),
+    ("SUCCESS", Align::Right, &["success_rate"]),
+    ("TREND", Align::Right, &["trend"]),
+    ("AVG DURATION", Align::Right, &["average_duration_seconds"]),
+    ("EXIT CODES", Align::Left, &["exit_codes"]),
+    (
+        "STATUS",
+        Align::Left,
+        &[
+            "broken_targets",
+            "stale",
+            "overdue",
+            "overlapping_tasks",
+            "triggered_without_task",
+            "stopped_tasks",
+        ],
+    ),
+    ("URL", Align::Left, &["console_url"]),
+];
+
+/// csv columns and the field they're derived from, where task columns are
+/// derived from stopped_tasks
+const CSV_COLUMNS: &[(&str, &str)] = &[
+    ("rule", "rule"),
+    ("arn", "arn"),
+    ("state", "state"),
+    ("task_definitions", "task_definitions"),
+    ("broken_targets", "broken_targets"),
+    ("target_type", "target_type"),
+    ("lambda_functions", "lambda_functions"),
+    ("schedule", "schedule"),
+    ("last_trigger", "last_trigger"),
+    ("next_run", "next_run"),
+    ("trigger_count", "trigger_count"),
+    ("max_burst", "max_burst"),
+    ("trigger_statistic", "trigger_statistic"),
+    ("daily_triggers", "daily_triggers"),
+    ("failed_invocations", "failed_invocations"),
+    ("success_rate", "success_rate"),
+    ("previous_trigger_count", "trend"),
+    ("trigger_change_percent", "trend"),
+    ("stale", "stale"),
+    ("overdue", "overdue"),
+    ("overlapping_tasks", "overlapping_tasks"),
+    ("triggered_without_task", "triggered_without_task"),
+    ("console_url", "console_url"),
+    ("average_duration_seconds", "average_duration_seconds"),
+    ("exit_code_counts", "exit_codes"),
+    ("task_failures", "task_failures"),
+    ("task_arn", "stopped_tasks"),
+    ("cluster", "stopped_tasks"),
+    ("last_status", "stopped_tasks"),
+    ("stopped_reason", "stopped_tasks"),
+    ("started_at", "stopped_tasks"),
+    ("stopped_at", "stopped_tasks"),
+    ("duration_seconds", "stopped_tasks"),
+    ("cpu", "stopped_tasks"),
+    ("memory", "stopped_tasks"),
+    ("out_of_memory", "stopped_tasks"),
+    ("launch_type", "stopped_tasks"),
+    ("capacity_provider", "stopped_tasks"),
+    ("spot_interrupted", "stopped_tasks"),
+    ("exit_codes", "stopped_tasks"),
+    ("task_console_url", "stopped_tasks"),
+];
+
+/// ansi escape to clear the screen and move the cursor home
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// how often the progress bar's spinner ticks while waiting on AWS
+const PROGRESS_TICK: StdDuration = StdDuration::from_millis(100);
+
+/// where a run's credentials come from, so results cached for one account
+/// or role aren't served to another
+struct Identity {
+    profile: Option<String>,
+    imds: bool,
+    assume_role_arn: Option<String>,
+    external_id: Option<String>,
+    /// the account the credentials belong to, when it could be looked up
+    account: Option<String>,
+}
+
+/// clients and options needed to run the rule, trigger, and task pipeline
+struct Pipeline<E = EventBridgeClient, M = CloudWatchClient, C = EcsClient> {
+    events: E,
+    metrics: M,
+    ecs: C,
+    region: Region,
+    identity: Identity,
+    bus: Option<String>,
+    prefixes: Vec<String>,
+    /// exact names of rules to describe rather than list by prefix
+    names: Vec<String>,
+    exact: bool,
+    tags: Vec<TagFilter>,
+    /// tags looked up so far, by rule arn
+    rule_tags: StdMutex<HashMap<String, HashMap<String, String>>>,
+    state: RuleState,
+    query: MetricQuery,
+    /// also count triggers in the preceding window to compare against
+    trend: bool,
+    clusters: Vec<String>,
+    /// template of the startedBy tag of each rule's tasks
+    started_by: String,
+    status: Status,
+    /// max number of each rule's most recent tasks to fetch
+    task_limit: Option<usize>,
+    family: Option<String>,
+    reason_filter: Option<Regex>,
+    /// name of the only container to report on in each task
+    container: Option<String>,
+    concurrency: usize,
+    /// max delay before each rule's metric queries
+    jitter: StdDuration,
+    retries: Retries,
+    /// show a progress bar on stderr while querying
+    progress: bool,
+    /// how long each run may take before abandoning the rules not yet queried
+    timeout: Option<StdDuration>,
+    /// how long a single rule's calls to each api may take before it's
+    /// reported as timed out
+    rule_timeout: Option<StdDuration>,
+    cache: Option<Cache>,
+}
+
+impl<E: EventsApi, M: MetricsApi, C: EcsApi> Pipeline<E, M, C> {
+    /// resolve matching rules, their triggers, and their tasks, from --cache
+    /// when fresh, failing only when rules can't be listed at all
+    async fn run(&self) -> Result<Outcome, CronitorError> {
+        self.run_with(None).await
+    }
+
+    /// like `run`, also calling `on_result` with each rule's result as soon
+    /// as it's gathered
+    async fn run_with(
+        &self,
+        on_result: Option<&(dyn Fn(&RuleResult) + Sync)>,
+    ) -> Result<Outcome, CronitorError> {
+        let key = self.cache_key();
+        if let Some(results) = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.get::<Vec<RuleResult>>(&key))
+        {
+            if let Some(on_result) = on_result {
+                results.iter().for_each(on_result);
+            }
+            return Ok(Outcome {
+                results,
+                errors: Vec::new(),
+            });
+        }
+        let outcome = self.query(on_result).await?;
+        // partial results are left uncached so the next run retries them
+        if let Some(cache) = self.cache.as_ref().filter(|_| outcome.errors.is_empty()) {
+            cache.put(&key, &outcome.results);
+        }
+        Ok(outcome)
+    }
+
+    /// everything that determines the results of a run
+    fn cache_key(&self) -> String {
+        format!(
+            "{:?}",
+            (
+                (
+                    self.region.name(),
+                    &self.identity.profile,
+                    self.identity.imds,
+                    &self.identity.assume_role_arn,
+                    &self.identity.external_id,
+                    &self.identity.account,
+                ),
+                &self.bus,
+                &self.prefixes,
+                &self.names,
+                self.exact,
+                &self.tags,
+                self.state,
+                &self.query,
+                self.trend,
+                (&self.clusters, &self.started_by),
+                (self.status, self.task_limit),
+                (
+                    &self.family,
+                    self.reason_filter.as_ref().map(Regex::as_str),
+                    &self.container,
+                ),
+            )
+        )
+    }
+
+    /// query AWS for the results of a run, bypassing the cache
+    async fn query(
+        &self,
+        on_result: Option<&(dyn Fn(&RuleResult) + Sync)>,
+    ) -> Result<Outcome, CronitorError> {
+        let deadline = Deadline {
+            run: self.timeout.map(|timeout| Instant::now() + timeout),
+            rule: self.rule_timeout,
+        };
+        let mut seen = HashSet::new();
+        let mut rules = Vec::new();
+        if !self.names.is_empty() {
+            rules = within(
+                deadline.run,
+                describe_rules(
+                    &self.events,
+                    self.bus.as_deref(),
+                    &self.names,
+                    self.state,
+                    self.concurrency,
+                    &self.retries,
+                ),
+            )
+            .await?;
+        }
+        for prefix in &self.prefixes {
+            let matching = within(
+                deadline.run,
+                list_matching_rules(&self.events, self.bus.as_deref(), prefix, self.state),
+            )
+            .await?;
+            for matched in matching {
+                if seen.insert(matched.name.clone()) {
+                    rules.push(matched);
+                }
+            }
+        }
+        // rules named by --rules-from are matched exactly already
+        if self.exact {
+            rules.retain(|matched| {
+                self.names.contains(&matched.name) || self.prefixes.contains(&matched.name)
+            });
+        }
+        let (rules, mut errors) = if self.tags.is_empty() {
+            (rules, Vec::new())
+        } else {
+            filter_by_tags(
+                &self.events,
+                rules,
+                &self.tags,
+                &self.rule_tags,
+                self.concurrency,
+                &self.retries,
+                deadline,
+            )
+            .await
+        };
+        let progress = self.progress_bar(rules.len() + errors.len());
+        progress.inc(errors.len() as u64);
+        progress.set_message("looking up targets");
+        let (rules, target_errors) = with_targets(
+            &self.events,
+            &self.ecs,
+            rules,
+            self.concurrency,
+            &self.retries,
+            deadline,
+        )
+        .await;
+        progress.inc(target_errors.len() as u64);
+        for (started_by, names) in started_by_collisions(&rules, &self.started_by) {
+            progress.suspend(|| {
+                eprintln!(
+                    "warning: rules {} share the startedBy tag {}, so their tasks may be attributed to each other",
+                    names.join(", "),
+                    started_by
+                )
+            });
+        }
+        progress.set_message("querying triggers");
+        let (triggers, trigger_errors) = last_triggers(
+            &self.metrics,
+            rules,
+            &self.query,
+            self.concurrency,
+            self.jitter,
+            &self.retries,
+            deadline,
+            self.trend,
+        )
+        .await;
+        progress.inc(trigger_errors.len() as u64);
+        progress.set_message("querying tasks");
+        // results are filtered before they're passed on, as they are after
+        let filtered = |result: &RuleResult| {
+            progress.inc(1);
+            if let Some(on_result) = on_result {
+                let (rule, triggers, mut tasks) = result.clone();
+                self.filter_tasks(&mut tasks);
+                on_result(&(rule, triggers, tasks));
+            }
+        };
+        let (mut results, task_errors) = stopped_tasks_for_rules(
+            &self.ecs,
+            &self.clusters,
+            &self.started_by,
+            triggers,
+            self.status,
+            self.task_limit,
+            self.concurrency,
+            &self.retries,
+            deadline,
+            &filtered,
+        )
+        .await;
+        progress.finish_and_clear();
+        errors.extend(target_errors);
+        errors.extend(trigger_errors);
+        errors.extend(task_errors);
+        for (_, _, tasks) in &mut results {
+            self.filter_tasks(tasks);
+        }
+        Ok(Outcome { results, errors })
+    }
+
+    /// a bar advancing as each of `rules` is queried, hidden unless
+    /// progress was requested
+    fn progress_bar(
+        &self,
+        rules: usize,
+    ) -> ProgressBar {
+        if !self.progress {
+            return ProgressBar::hidden();
+        }
+        let progress = ProgressBar::new(rules as u64).with_style(
+            ProgressStyle::with_template("{spinner} {bar:40} {pos}/{len} rules, {msg}")
+                .expect("invalid progress template"),
+        );
+        progress.enable_steady_tick(PROGRESS_TICK);
+        progress
+    }
+
+    /// keep only the tasks matching --family and --reason-filter, and only
+    /// the containers named by --container
+    fn filter_tasks(
+        &self,
+        tasks: &mut Vec<Task>,
+    ) {
+        if let Some(family) = &self.family {
+            tasks.retain(|task| {
+                task.task_definition_arn.as_deref().and_then(task_family) == Some(family.as_str())
+            });
+        }
+        if let Some(reason_filter) = &self.reason_filter {
+            tasks.retain(|task| {
+                task.stopped_reason
+                    .as_deref()
+                    .map(|reason| reason_filter.is_match(reason))
+                    .unwrap_or_default()
+            });
+        }
+        if let Some(name) = &self.container {
+            for task in tasks.iter_mut() {
+                if let Some(containers) = &mut task.containers {
+                    containers.retain(|container| container.name.as_ref() == Some(name));
+                }
+            }
+        }
+    }
+}
+
+/// the rules the pipeline gathered the history of, and those it failed to
+struct Outcome {
+    results: Vec<RuleResult>,
+    errors: Vec<RuleError>,
+}
+
+/// block characters of increasing height used to render sparklines
+const SPARKS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// render counts as a sparkline scaled to the largest, where only zero
+/// counts get the lowest block and the largest gets the highest
+fn sparkline_of(counts: &[u64]) -> String {
+    let max = counts.iter().copied().max().unwrap_or_default();
+    let top = SPARKS.len() as u64 - 1;
+    counts
+        .iter()
+        .map(|count| match *count {
+            0 => SPARKS[0],
+            _ if max == 1 => SPARKS[top as usize],
+            count => SPARKS[(1 + (count - 1) * (top - 1) / (max - 1)) as usize],
+        })
+        .collect()
+}
+
+/// render each rule's daily triggers as a sparkline, oldest day first
+fn sparklines(reports: &[RuleReport]) -> String {
+    let mut out = String::from("\nDAILY TRIGGERS:\n");
+    for report in reports {
+        out.push_str(&format!(
+            "    {} {}\n",
+            sparkline_of(&report.daily_triggers),
+            report.rule
+        ));
+    }
+    out
+}
+
+/// render the fetched logs of failed containers, if any, indented under
+/// the task they belong to
+fn logs(reports: &[RuleReport]) -> String {
+    let mut out = String::new();
+    for report in reports {
+        for task in &report.stopped_tasks {
+            for container in task.containers.iter().filter(|c| !c.logs.is_empty()) {
+                out.push_str(&format!(
+                    "    {} {} {} (exit code {}):\n",
+                    report.rule,
+                    task.task_arn
+                        .as_deref()
+                        .and_then(|arn| arn.rsplit('/').next())
+                        .unwrap_or_default(),
+                    container.name.as_deref().unwrap_or_default(),
+                    container.exit_code.unwrap_or_default()
+                ));
+                for line in &container.logs {
+                    out.push_str(&format!("        {}\n", line));
+                }
+            }
+        }
+    }
+    if out.is_empty() {
+        out
+    } else {
+        format!("\nLOGS:\n{}", out)
+    }
+}
+
+/// render the tasks ecs listed but couldn't describe, if any, explaining
+/// why a rule has fewer tasks than were listed
+fn undescribed(reports: &[RuleReport]) -> String {
+    let mut out = String::new();
+    for report in reports {
+        for failure in &report.task_failures {
+            out.push_str(&format!(
+                "    {} {} in {}: {}{}\n",
+                report.rule,
+                failure
+                    .arn
+                    .as_deref()
+                    .and_then(|arn| arn.rsplit('/').next())
+                    .unwrap_or_default(),
+                failure.cluster,
+                failure.reason.as_deref().unwrap_or("unknown reason"),
+                failure
+                    .detail
+                    .as_deref()
+                    .map(|detail| format!(" ({})", detail))
+                    .unwrap_or_default()
+            ));
+        }
+    }
+    if out.is_empty() {
+        out
+    } else {
+        format!("\nUNDESCRIBED TASKS:\n{}", out)
+    }
+}
+
+/// render the rules we failed to query, if any
+fn errors(errors: &[RuleError]) -> String {
+    if errors.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from("\nERRORS:\n");
+    for err in errors {
+        out.push_str(&format!("    {}: {}\n", err.rule, err.error));
+    }
+    out
+}
+
+/// content type of the prometheus text exposition format
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// content type of the openmetrics text format
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// text formats metrics can be exposed in
+#[derive(Clone, Copy, PartialEq)]
+enum Exposition {
+    Prometheus,
+    OpenMetrics,
+}
+
+impl Exposition {
+    /// the format a scraper asked for in its accept header, preferring the
+    /// legacy prometheus format when it didn't ask for openmetrics
+    fn negotiate(request: &Request<Body>) -> Self {
+        let accepts_openmetrics = request
+            .headers()
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|accept| accept.to_str().ok())
+            .flat_map(|accept| accept.split(','))
+            .any(|media| {
+                media
+                    .trim()
+                    .to_ascii_lowercase()
+                    .starts_with("application/openmetrics-text")
+            });
+        if accepts_openmetrics {
+            Exposition::OpenMetrics
+        } else {
+            Exposition::Prometheus
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Exposition::Prometheus => PROMETHEUS_CONTENT_TYPE,
+            Exposition::OpenMetrics => OPENMETRICS_CONTENT_TYPE,
+        }
+    }
+}
+
+/// escape a prometheus label value
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// a gauge's name, help text, unit, and per rule value, omitted when `None`
+type Gauge<'a> = (
+    &'a str,
+    &'a str,
+    Option<&'a str>,
+    &'a dyn Fn(&RuleReport) -> Option<f64>,
+);
+
+/// render reports in the prometheus text exposition format, or in the
+/// openmetrics format with each sample stamped with when it was gathered
+fn prometheus(
+    reports: &[RuleReport],
+    errors: &[RuleError],
+    format: Exposition,
+    gathered: DateTime<Utc>,
+) -> String {
+    let epoch = |ts: &str| {
+        DateTime::parse_from_rfc3339(ts)
+            .ok()
+            .map(|time| time.timestamp() as f64)
+    };
+    let gauges: &[Gauge] = &[
+        (
+            "cron_last_trigger_timestamp_seconds",
+            "unix time of the start of the most recent trigger datapoint",
+            Some("seconds"),
+            &|report| report.last_trigger.as_deref().and_then(epoch),
+        ),
+        (
+            "cron_next_run_timestamp_seconds",
+            "unix time the rule's schedule next fires",
+            Some("seconds"),
+            &|report| report.next_run.as_deref().and_then(epoch),
+        ),
+        (
+            "cron_trigger_count",
+            "number of triggers within the query window",
+            None,
+            &|report| Some(report.trigger_count as f64),
+        ),
+        (
+            "cron_max_trigger_burst",
+            "most triggers within a single minute of the query window",
+            None,
+            &|report| report.max_burst.map(|burst| burst as f64),
+        ),
+        (
+            "cron_trigger_statistic",
+            "the --statistic of triggers within the query window",
+            None,
+            &|report| report.trigger_statistic,
+        ),
+        (
+            "cron_failed_invocation_count",
+            "number of failed target invocations within the query window",
+            None,
+            &|report| Some(report.failed_invocations as f64),
+        ),
+        (
+            "cron_stopped_task_count",
+            "number of tasks started by the rule",
+            None,
+            &|report| Some(report.stopped_tasks.len() as f64),
+        ),
+        (
+            "cron_failed_task_count",
+            "number of tasks started by the rule with a non-zero exit code",
+            None,
+            &|report| {
+                Some(
+                    report
+                        .stopped_tasks
+                        .iter()
+                        .filter(|task| task.failed())
+                        .count() as f64,
+                )
+            },
+        ),
+        (
+            "cron_stale",
+            "1 when the rule has not triggered within --max-age",
+            None,
+            &|report| Some(f64::from(u8::from(report.stale))),
+        ),
+        (
+            "cron_overdue",
+            "1 when the rule's schedule should have fired since its last trigger",
+            None,
+            &|report| Some(f64::from(u8::from(report.overdue))),
+        ),
+        (
+            "cron_triggered_without_task",
+            "1 when the rule recently triggered but no task it started was found",
+            None,
+            &|report| Some(f64::from(u8::from(report.triggered_without_task))),
+        ),
+        (
+            "cron_average_task_duration_seconds",
+            "mean run time of the rule's stopped tasks",
+            Some("seconds"),
+            &|report| report.average_duration_seconds.map(f64::trunc),
+        ),
+        (
+            "cron_lambda_invocation_count",
+            "number of invocations of the rule's lambda functions within the query window",
+            None,
+            &|report| report.lambda_invocations.map(|count| count as f64),
+        ),
+        (
+            "cron_lambda_error_count",
+            "number of the rule's lambda invocations which failed within the query window",
+            None,
+            &|report| report.lambda_errors.map(|count| count as f64),
+        ),
+        (
+            "cron_undescribed_task_count",
+            "number of the rule's listed tasks which ecs failed to describe",
+            None,
+            &|report| Some(report.task_failures.len() as f64),
+        ),
+        (
+            "cron_broken_target_count",
+            "number of the rule's task definitions which were deleted or are inactive",
+            None,
+            &|report| Some(report.broken_targets.len() as f64),
+        ),
+        (
+            "cron_overlapping_task_count",
+            "number of the rule's tasks running at once, when more than one is",
+            None,
+            &|report| Some(report.overlapping_tasks.len() as f64),
+        ),
+    ];
+    let timestamp = match format {
+        Exposition::OpenMetrics => format!(" {}", gathered.timestamp()),
+        Exposition::Prometheus => String::new(),
+    };
+    let mut out = String::from(
+        "# HELP cron_query_error 1 when the rule's history could not be queried\n# TYPE cron_query_error gauge\n",
+    );
+    for err in errors {
+        out.push_str(&format!(
+            "cron_query_error{{rule=\"{}\"}} 1{}\n",
+            label(&err.rule),
+            timestamp
+        ));
+    }
+    for (name, help, unit, value) in gauges {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} gauge\n",
+            name, help, name
+        ));
+        if let (Exposition::OpenMetrics, Some(unit)) = (format, unit) {
+            out.push_str(&format!("# UNIT {} {}\n", name, unit));
+        }
+        for report in reports {
+            if let Some(value) = value(report) {
+                out.push_str(&format!(
+                    "{}{{rule=\"{}\"}} {}{}\n",
+                    name,
+                    label(&report.rule),
+                    value,
+                    timestamp
+                ));
+            }
+        }
+    }
+    if format == Exposition::OpenMetrics {
+        out.push_str("# EOF\n");
+    }
+    out
+}
+
+/// reports gathered for a scrape, kept to render in either format
+struct Scrape {
+    at: DateTime<Utc>,
+    reports: Vec<RuleReport>,
+    errors: Vec<RuleError>,
+}
+
+/// serves the pipeline's results as prometheus metrics
+struct Exporter {
+    pipeline: Pipeline,
+    max_age: Option<Duration>,
+    cache: Option<StdDuration>,
+    /// the most recently gathered reports and when they were gathered
+    cached: Mutex<Option<(Instant, Arc<Scrape>)>>,
+    health: StdMutex<Health>,
+}
+
+/// the outcome of the exporter's refreshes, served on /health
+#[derive(Default, Serialize)]
+struct Health {
+    /// when AWS was last queried successfully
+    last_success: Option<DateTime<Utc>>,
+    /// why the last refresh failed, including when it failed to query every
+    /// rule, cleared once one succeeds
+    last_error: Option<String>,
+    last_error_at: Option<DateTime<Utc>>,
+}
+
+impl Exporter {
+    /// render metrics, querying AWS unless a cached copy is fresh enough
+    async fn metrics(
+        &self,
+        format: Exposition,
+    ) -> Result<String, CronitorError> {
+        let scrape = self.scrape().await?;
+        Ok(prometheus(
+            &scrape.reports,
+            &scrape.errors,
+            format,
+            scrape.at,
+        ))
+    }
+
+    async fn scrape(&self) -> Result<Arc<Scrape>, CronitorError> {
+        // holding the lock while querying collapses concurrent scrapes into one
+        let mut cached = self.cached.lock().await;
+        if let (Some(cache), Some((at, scrape))) = (self.cache, cached.as_ref()) {
+            if at.elapsed() < cache {
+                return Ok(scrape.clone());
+            }
+        }
+        let outcome = self.pipeline.run().await;
+        {
+            let mut health = self.health.lock().expect("health lock poisoned");
+            match &outcome {
+                Ok(Outcome { results, errors }) if results.is_empty() && !errors.is_empty() => {
+                    health.last_error = Some(format!(
+                        "failed to query every rule, e.g. {}: {}",
+                        errors[0].rule, errors[0].error
+                    ));
+                    health.last_error_at = Some(Utc::now());
+                }
+                Ok(_) => {
+                    health.last_success = Some(Utc::now());
+                    health.last_error = None;
+                    health.last_error_at = None;
+                }
+                Err(err) => {
+                    health.last_error = Some(err.to_string());
+                    health.last_error_at = Some(Utc::now());
+                }
+            }
+        }
+        let Outcome { results, errors } = outcome?;
+        let reports = results
+            .into_iter()
+            .map(|(rule, triggers, tasks)| {
+                RuleReport::new(
+                    rule,
+                    triggers,
+                    &tasks,
+                    self.pipeline.status,
+                    &self.pipeline.region,
+                    &self.pipeline.query,
+                    self.max_age,
+                    Timezone::Utc,
+                )
+            })
+            .collect::<Vec<_>>();
+        let scrape = Arc::new(Scrape {
+            at: Utc::now(),
+            reports,
+            errors,
         });
+        *cached = Some((Instant::now(), scrape.clone()));
+        Ok(scrape)
+    }
+
+    /// 200 once a refresh has succeeded and unless the last one failed, with
+    /// when AWS was last queried successfully and why the last refresh failed
+    fn health(&self) -> Response<Body> {
+        let health = self.health.lock().expect("health lock poisoned");
+        Response::builder()
+            .status(
+                if health.last_success.is_some() && health.last_error.is_none() {
+                    StatusCode::OK
+                } else {
+                    StatusCode::SERVICE_UNAVAILABLE
+                },
+            )
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(json_line(&*health)))
+            .expect("invalid response")
+    }
+
+    async fn respond(
+        self: Arc<Self>,
+        request: Request<Body>,
+    ) -> Result<Response<Body>, Infallible> {
+        if request.uri().path() == "/health" {
+            return Ok(self.health());
+        }
+        if request.uri().path() != "/metrics" {
+            return Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("not found\n"))
+                .expect("invalid response"));
+        }
+        let format = Exposition::negotiate(&request);
+        Ok(match self.metrics(format).await {
+            Ok(metrics) => Response::builder()
+                .header(CONTENT_TYPE, format.content_type())
+                .body(Body::from(metrics)),
+            Err(err) => {
+                error!("failed to gather metrics: {}", err);
+                Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from(format!("{}\n", err)))
+            }
+        }
+        .expect("invalid response"))
+    }
+}
+
+/// channels notified of unhealthy rules
+struct Notifier {
+    slack_webhook: Option<String>,
+    sns: Option<(SnsClient, String)>,
+    always_notify: bool,
+    pagerduty_routing_key: Option<String>,
+    pagerduty_resolve: bool,
+    /// publishes each rule's last trigger age when --emit-metrics is set
+    metrics: Option<CloudWatchClient>,
+    retries: Retries,
+}
 
-    let last_triggers = rules.and_then(move |names| {
-        let mets = std::sync::Arc::new(metrics);
-        join_all(names.into_iter().map(move |name| {
-            get_last_trigger(mets.clone(), name.as_str(), Duration::weeks(1)).map(|ts| (name, ts))
-        }))
+impl Notifier {
+    /// notify configured channels, reporting rather than failing on errors
+    /// so notification outages don't mask the report itself
+    async fn notify(
+        &self,
+        clusters: &[String],
+        report: &Report,
+    ) {
+        if let Some(webhook) = &self.slack_webhook {
+            if let Err(err) = notify::slack(webhook, clusters, &report.rules).await {
+                eprintln!("error: failed to notify slack: {}", err);
+            }
+        }
+        if let Some((sns, topic_arn)) = &self.sns {
+            if let Err(err) =
+                notify::sns(sns, topic_arn, clusters, report, self.always_notify).await
+            {
+                eprintln!("error: failed to publish to {}: {}", topic_arn, err);
+            }
+        }
+        if let Some(routing_key) = &self.pagerduty_routing_key {
+            if let Err(err) =
+                notify::pagerduty(routing_key, clusters, &report.rules, self.pagerduty_resolve)
+                    .await
+            {
+                eprintln!("error: failed to notify pagerduty: {}", err);
+            }
+        }
+        if let Some(metrics) = &self.metrics {
+            if let Err(err) = notify::metrics(metrics, &report.rules, &self.retries).await {
+                eprintln!("error: failed to publish metrics: {}", err);
+            }
+        }
+    }
+}
+
+/// read newline separated rule names from a file, or stdin given -,
+/// skipping blank lines and # comments
+fn rule_names(path: &Path) -> Vec<String> {
+    let contents = if path == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())
+    } else {
+        std::fs::read_to_string(path)
+    }
+    .unwrap_or_else(|err| {
+        fail(
+            format!("failed to read {}: {}", path.display(), err),
+            EXIT_IO_ERROR,
+        )
     });
+    let mut seen = HashSet::new();
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && !name.starts_with('#'))
+        .filter(|name| seen.insert(*name))
+        .map(String::from)
+        .collect()
+}
 
-    let stopped_tasks = last_triggers.and_then(move |triggers| {
-        let ecss = std::sync::Arc::new(ecs);
-        join_all(triggers.into_iter().map(move |(rule, last)| {
-            let cluster = cluster.clone();
-            let ecs = ecss.clone();
-            let ecs2 = ecss.clone();
-            ecs.list_tasks(ListTasksRequest {
-                cluster: Some(cluster.clone()),
-                desired_status: Some("STOPPED".into()),
-                started_by: Some(format!("events-rule/{}", rule).chars().take(36).collect()),
-                ..ListTasksRequest::default()
-            })
-            .map_err(|e| e.to_string())
-            .and_then(move |response| {
-                ecs2.describe_tasks(DescribeTasksRequest {
-                    cluster: Some(cluster),
-                    tasks: response.task_arns.unwrap_or_default(),
+/// write rendered output to a file, or stdout when no path is given
+fn write_output(
+    path: Option<&Path>,
+    contents: &str,
+) {
+    match path {
+        Some(path) => {
+            if let Err(err) = std::fs::write(path, contents) {
+                fail(
+                    format!("failed to write {}: {}", path.display(), err),
+                    EXIT_IO_ERROR,
+                )
+            }
+        }
+        _ => print!("{}", contents),
+    }
+}
+
+/// serialize a value as a single line of json
+fn json_line(value: &impl Serialize) -> String {
+    format!(
+        "{}\n",
+        serde_json::to_string(value).expect("failed to serialize output")
+    )
+}
+
+/// exit the process after reporting an error
+fn fail(
+    err: impl std::fmt::Display,
+    code: i32,
+) -> ! {
+    eprintln!("error: {}", err);
+    std::process::exit(code)
+}
+
+/// a rule is unhealthy when it's stale or overdue, failed to invoke its
+/// target or its target is broken, started no task or tasks which failed or
+/// overlapped, or triggered significantly less than in the preceding window
+fn unhealthy(report: &RuleReport) -> bool {
+    report.stale
+        || report.overdue
+        || report.triggered_without_task
+        || report.failed_invocations > 0
+        || report.stopped_tasks.iter().any(|task| task.failed())
+        || !report.overlapping_tasks.is_empty()
+        || report.trend.as_ref().is_some_and(|trend| trend.dropped)
+        || !report.broken_targets.is_empty()
+        || report.lambda_errors.unwrap_or_default() > 0
+}
+
+/// which rules `list` renders
+#[derive(Clone, Copy)]
+struct Listing {
+    only_stale: bool,
+    quiet: bool,
+    min_invocations: Option<u64>,
+    max_invocations: Option<u64>,
+}
+
+impl Listing {
+    fn lists(
+        &self,
+        report: &RuleReport,
+    ) -> bool {
+        (!self.only_stale || report.stale || report.last_trigger.is_none())
+            && (!self.quiet || unhealthy(report))
+            && self
+                .min_invocations
+                .is_none_or(|min| report.trigger_count >= min)
+            && self
+                .max_invocations
+                .is_none_or(|max| report.trigger_count <= max)
+    }
+
+    /// the rules of `report` to render, keeping its summary of every rule
+    fn shown(
+        &self,
+        report: &Report,
+    ) -> Report {
+        Report {
+            rules: report
+                .rules
+                .iter()
+                .filter(|rule| self.lists(rule))
+                .cloned()
+                .collect(),
+            errors: report.errors.clone(),
+            summary: report.summary.clone(),
+        }
+    }
+}
+
+/// config keys whose option is named differently from its long flag
+const CONFIG_RENAMED: &[(&str, &str)] = &[
+    ("rule", "prefixes"),
+    ("cluster", "clusters"),
+    ("tag", "tags"),
+    ("cache", "cache_dir"),
+    ("started-by-prefix", "started_by"),
+];
+
+/// pairs of options which conflict, so a config default for either is
+/// dropped when the other is given on the command line
+const CONFIG_CONFLICTS: &[(&str, &str)] = &[("prefixes", "rules_from"), ("imds", "profile")];
+
+/// parse options from the command line, falling back on the config file
+/// for any global option not given there
+fn options() -> Options {
+    let args = std::env::args_os().collect::<Vec<_>>();
+    let matches = Options::clap().get_matches_from(&args);
+    let options = Options::from_clap(&matches);
+    let path = match options.config.clone().or_else(config::default_path) {
+        Some(path) => path,
+        _ => return options,
+    };
+    let table = config::load(&path).unwrap_or_else(|err| fail(err, EXIT_USAGE));
+    let defaults = config::args(&table, |key| {
+        let name = CONFIG_RENAMED
+            .iter()
+            .find(|(flag, _)| *flag == key)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_else(|| key.replace('-', "_"));
+        let given = |name: &str| matches.occurrences_of(name) > 0;
+        given(&name)
+            || CONFIG_CONFLICTS
+                .iter()
+                .any(|(a, b)| (*a == name && given(b)) || (*b == name && given(a)))
+    })
+    .unwrap_or_else(|err| fail(err, EXIT_USAGE));
+    // defaults go before the subcommand so they're parsed as global options
+    Options::from_iter(
+        args.iter()
+            .take(1)
+            .cloned()
+            .chain(defaults.into_iter().map(OsString::from))
+            .chain(args.iter().skip(1).cloned()),
+    )
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let Options {
+        config: _,
+        prefixes,
+        rules_from,
+        bus,
+        tags,
+        clusters,
+        started_by,
+        since,
+        start,
+        end,
+        region,
+        profile,
+        status,
+        task_limit,
+        overlap_check,
+        trend,
+        debug,
+        validate,
+        concurrency,
+        jitter,
+        max_retries,
+        retry_on,
+        max_age,
+        timezone,
+        credential_timeout,
+        imds,
+        metadata_timeout,
+        connect_timeout,
+        read_timeout,
+        timeout,
+        rule_timeout,
+        assume_role_arn,
+        external_id,
+        period,
+        statistic,
+        state,
+        exact,
+        family,
+        reason_filter,
+        container,
+        slack_webhook,
+        sns_topic_arn,
+        always_notify,
+        pagerduty_routing_key,
+        pagerduty_resolve,
+        emit_metrics,
+        cache_dir,
+        cache_ttl,
+        refresh,
+        namespace,
+        dimension_name,
+        command,
+    } = options();
+    if let Command::Completions { shell } = command {
+        Options::clap().gen_completions_to("cronitor", shell, &mut std::io::stdout());
+        return;
+    }
+    let names = rules_from.as_deref().map(rule_names).unwrap_or_default();
+    if (prefixes.is_empty() && names.is_empty()) || clusters.is_empty() {
+        fail(
+            "--rule or --rules-from and --cluster are required",
+            EXIT_USAGE,
+        );
+    }
+    let since = match start {
+        Some(start) => {
+            let until = end.unwrap_or_else(Utc::now);
+            if start >= until {
+                fail("--start must precede --end", EXIT_USAGE);
+            }
+            until - start
+        }
+        _ => since,
+    };
+    let query = MetricQuery {
+        since,
+        period,
+        statistic,
+        namespace,
+        dimension_name,
+        end,
+    };
+    if let Err(err) = query.validate() {
+        fail(err, EXIT_USAGE);
+    }
+    match command {
+        Command::List { fail_on_stale, .. } if fail_on_stale && max_age.is_none() => {
+            fail("--fail-on-stale requires --max-age", EXIT_USAGE)
+        }
+        Command::List {
+            output: Output::Nagios,
+            ..
+        } if max_age.is_none() => fail("nagios output requires --max-age", EXIT_USAGE),
+        Command::List {
+            output: Output::Csv | Output::Debug | Output::Jsonl | Output::Nagios,
+            group_by: Some(_),
+            ..
+        } => fail(
+            "--group-by requires table, markdown, json, or yaml output",
+            EXIT_USAGE,
+        ),
+        Command::Check if max_age.is_none() => fail("check requires --max-age", EXIT_USAGE),
+        _ => (),
+    }
+    let nagios_plugin = matches!(
+        command,
+        Command::List {
+            output: Output::Nagios,
+            ..
+        }
+    );
+    dump_responses(debug);
+    let region = region.unwrap_or_default();
+    let dispatcher = Dispatcher::new(
+        connect_timeout.map(|timeout| timeout.to_std().unwrap_or_default()),
+        read_timeout.map(|timeout| timeout.to_std().unwrap_or_default()),
+    );
+    let creds = credentials(
+        profile.clone(),
+        StdDuration::from_millis(credential_timeout),
+        Some(StdDuration::from_millis(metadata_timeout)).filter(|_| imds),
+    )
+    .unwrap_or_else(|err| query_failed(err, nagios_plugin));
+    let creds = match assume_role_arn.clone() {
+        Some(role_arn) => assume_role(
+            dispatcher.clone(),
+            creds,
+            region.clone(),
+            role_arn,
+            external_id.clone(),
+        )
+        .unwrap_or_else(|err| query_failed(err, nagios_plugin)),
+        _ => creds,
+    };
+
+    let retries = if retry_on.is_empty() {
+        Retries::throttled(max_retries)
+    } else {
+        Retries {
+            max: max_retries,
+            on: retry_on,
+        }
+    };
+    let metrics = CloudWatchClient::new_with(dispatcher.clone(), creds.clone(), region.clone());
+    let notifier = Notifier {
+        slack_webhook,
+        sns: sns_topic_arn.map(|topic_arn| {
+            let sns = SnsClient::new_with(dispatcher.clone(), creds.clone(), region.clone());
+            (sns, topic_arn)
+        }),
+        always_notify,
+        pagerduty_routing_key,
+        pagerduty_resolve,
+        metrics: Some(metrics.clone()).filter(|_| emit_metrics),
+        retries: retries.clone(),
+    };
+    // only cached results need telling apart by account
+    let account = match &cache_dir {
+        Some(_) => {
+            match account_id(dispatcher.clone(), creds.clone(), region.clone(), &retries).await {
+                Ok(account) => Some(account),
+                Err(err) => {
+                    warn!(
+                        "failed to look up the account to cache results under: {}",
+                        err
+                    );
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    let s3 = S3Client::new_with(dispatcher.clone(), creds.clone(), region.clone());
+    let log_events =
+        CloudWatchLogsClient::new_with(dispatcher.clone(), creds.clone(), region.clone());
+    let pipeline = Pipeline {
+        events: EventBridgeClient::new_with(dispatcher.clone(), creds.clone(), region.clone()),
+        metrics,
+        ecs: EcsClient::new_with(dispatcher.clone(), creds.clone(), region.clone()),
+        region,
+        identity: Identity {
+            profile,
+            imds,
+            assume_role_arn,
+            external_id,
+            account,
+        },
+        bus,
+        prefixes,
+        names,
+        exact,
+        tags,
+        rule_tags: StdMutex::new(HashMap::new()),
+        state,
+        query,
+        trend,
+        clusters,
+        started_by,
+        // running tasks are needed to tell whether runs overlap
+        status: match status {
+            Status::Stopped if overlap_check => Status::All,
+            status => status,
+        },
+        task_limit,
+        family,
+        reason_filter,
+        container,
+        concurrency,
+        jitter: StdDuration::from_millis(jitter),
+        retries,
+        // machine readable output is likely being piped somewhere that
+        // doesn't want a progress bar interleaved with it
+        progress: std::io::stderr().is_terminal()
+            && match &command {
+                Command::List { output, quiet, .. } => {
+                    !quiet && matches!(output, Output::Debug | Output::Markdown | Output::Table)
+                }
+                Command::Check => true,
+                _ => false,
+            },
+        timeout: timeout.map(|timeout| timeout.to_std().unwrap_or_default()),
+        rule_timeout: rule_timeout.map(|timeout| timeout.to_std().unwrap_or_default()),
+        cache: cache_dir
+            .map(|dir| Cache::new(dir, cache_ttl.to_std().unwrap_or_default(), refresh)),
+    };
+    if validate {
+        let checks = validate_access(&pipeline.events, &pipeline.metrics, &pipeline.ecs).await;
+        for (api, access) in &checks {
+            match access {
+                Ok(()) => println!("{}: ok", api),
+                Err(err) => println!("{}: {}", api, err),
+            }
+        }
+        if checks.iter().any(|(_, access)| access.is_err()) {
+            std::process::exit(EXIT_AWS_ERROR);
+        }
+        return;
+    }
+    if let Err(err) = check_clusters(&pipeline.ecs, &pipeline.clusters, &pipeline.retries).await {
+        query_failed(err, nagios_plugin);
+    }
+    let to_report = |(rule, triggers, tasks): &(Rule, Triggers, Vec<Task>)| {
+        RuleReport::new(
+            rule.clone(),
+            triggers.clone(),
+            tasks,
+            pipeline.status,
+            &pipeline.region,
+            &pipeline.query,
+            max_age,
+            timezone,
+        )
+    };
+
+    match command {
+        Command::List {
+            output,
+            color,
+            only_stale,
+            quiet,
+            min_invocations,
+            max_invocations,
+            fail_on_stale,
+            watch,
+            output_file,
+            s3_uri,
+            tail,
+            fields,
+            sort_by,
+            group_by,
+            reverse,
+            sparkline,
+        } => {
+            let fields = fields.unwrap_or_else(Fields::all);
+            let listing = Listing {
+                only_stale,
+                quiet,
+                min_invocations,
+                max_invocations,
+            };
+            // jsonl is streamed unless it has to be sorted, tailed, or
+            // written to a file or s3 first
+            let stream = matches!(output, Output::Jsonl)
+                && sort_by.is_none()
+                && tail.is_none()
+                && output_file.is_none()
+                && s3_uri.is_none();
+            let plain = output_file.is_none() && s3_uri.is_none();
+            let emit = |result: &RuleResult| {
+                let report = to_report(result);
+                if listing.lists(&report) {
+                    print!("{}", json_line(&fields.select_rule(&report)));
+                }
+            };
+            let on_result: Option<&(dyn Fn(&RuleResult) + Sync)> =
+                if stream { Some(&emit) } else { None };
+            // watch stops at the next interrupt rather than cutting a run short
+            if watch.is_none() {
+                interrupt_on_ctrl_c();
+            }
+            let mut delay = None;
+            loop {
+                let run = async {
+                    if let Some(delay) = delay {
+                        tokio::time::sleep(delay).await;
+                    }
+                    pipeline.run_with(on_result).await
+                };
+                // every run of a watch, including the first, stops at the
+                // next interrupt
+                let outcome = if watch.is_some() {
+                    tokio::select! {
+                        _ = tokio::signal::ctrl_c() => break,
+                        outcome = run => outcome,
+                    }
+                } else {
+                    run.await
+                };
+                match outcome {
+                    Ok(Outcome {
+                        results,
+                        errors: failed,
+                    }) => {
+                        if watch.is_some()
+                            && output_file.is_none()
+                            && matches!(output, Output::Table)
+                        {
+                            print!("{}", CLEAR_SCREEN);
+                        }
+                        let mut gathered = results
+                            .into_iter()
+                            .map(|result| {
+                                let report = to_report(&result);
+                                (result, report)
+                            })
+                            .collect::<Vec<_>>();
+                        if let Some(sort_by) = sort_by {
+                            gathered.sort_by(|(_, a), (_, b)| sort_by.compare(a, b));
+                            if reverse {
+                                gathered.reverse();
+                            }
+                        }
+                        let (results, mut reports): (Vec<_>, Vec<_>) = gathered.into_iter().unzip();
+                        if let Some(lines) = tail {
+                            tail_failed_containers(
+                                &pipeline.ecs,
+                                &log_events,
+                                &mut reports,
+                                lines,
+                                pipeline.concurrency,
+                                &pipeline.retries,
+                            )
+                            .await;
+                        }
+                        let results = results
+                            .iter()
+                            .zip(&reports)
+                            .filter(|(_, report)| listing.lists(report))
+                            .map(|(result, _)| result)
+                            .collect::<Vec<_>>();
+                        // the summary, notifications, and exit codes cover
+                        // every rule, however few are rendered
+                        let report = Report::new(reports, failed);
+                        let shown = listing.shown(&report);
+                        let rendered = match output {
+                            Output::Debug => format!(
+                                "{:#?}\n{}{}{}{}\n",
+                                results,
+                                if sparkline {
+                                    sparklines(&shown.rules)
+                                } else {
+                                    String::new()
+                                },
+                                logs(&shown.rules),
+                                errors(&shown.errors),
+                                shown.summary
+                            ),
+                            Output::Table => format!(
+                                "{}{}{}{}\n{}\n",
+                                // never color output written to a file or s3
+                                table(
+                                    &shown.rules,
+                                    plain && color.enabled(),
+                                    &fields,
+                                    sparkline,
+                                    group_by,
+                                ),
+                                logs(&shown.rules),
+                                undescribed(&shown.rules),
+                                errors(&shown.errors),
+                                shown.summary
+                            ),
+                            Output::Json => format!(
+                                "{}\n",
+                                serde_json::to_string_pretty(&fields.select(&shown, group_by))
+                                    .expect("failed to serialize output")
+                            ),
+                            Output::Yaml => serde_yaml::to_string(&fields.select(&shown, group_by))
+                                .expect("failed to serialize output"),
+                            Output::Csv => csv(&shown.rules, &fields),
+                            Output::Jsonl => {
+                                // streamed rules were already written as they were gathered
+                                let rules = if stream { &[][..] } else { &shown.rules[..] };
+                                rules
+                                    .iter()
+                                    .map(|rule| json_line(&fields.select_rule(rule)))
+                                    .chain(shown.errors.iter().map(json_line))
+                                    .collect()
+                            }
+                            Output::Markdown => markdown(&shown, &fields, sparkline, group_by),
+                            Output::Nagios => format!("{}\n", nagios(&report).0),
+                        };
+                        // quiet runs say nothing unless something's wrong
+                        if !quiet || !shown.rules.is_empty() || !shown.errors.is_empty() {
+                            write_output(output_file.as_deref(), &rendered);
+                        }
+                        if let Some(uri) = &s3_uri {
+                            if let Err(err) = archive::upload(
+                                &s3,
+                                uri,
+                                &rendered,
+                                output.content_type(),
+                                output.extension(),
+                                Utc::now(),
+                                &pipeline.retries,
+                            )
+                            .await
+                            {
+                                eprintln!("error: failed to upload to {}: {}", uri, err);
+                            }
+                        }
+                        notifier.notify(&pipeline.clusters, &report).await;
+                        if matches!(output, Output::Nagios) && watch.is_none() {
+                            std::process::exit(nagios(&report).1);
+                        }
+                        if fail_on_stale && report.summary.stale > 0 {
+                            std::process::exit(EXIT_UNHEALTHY);
+                        }
+                        if let Some(code) = incomplete(&report).filter(|_| watch.is_none()) {
+                            std::process::exit(code);
+                        }
+                    }
+                    // a failed refresh shouldn't bring down a long running watch
+                    Err(err) if watch.is_some() => eprintln!("error: {}", err),
+                    Err(err) => query_failed(err, nagios_plugin),
+                }
+                delay = match watch {
+                    Some(interval) => Some(interval.to_std().unwrap_or_default()),
+                    _ => break,
+                };
+            }
+        }
+        Command::Check => {
+            interrupt_on_ctrl_c();
+            let Outcome { results, errors } =
+                pipeline.run().await.unwrap_or_else(|err| run_failed(err));
+            let report = Report::new(results.iter().map(to_report).collect(), errors);
+            notifier.notify(&pipeline.clusters, &report).await;
+            let problems = report
+                .rules
+                .iter()
+                .filter(|rule| unhealthy(rule))
+                .collect::<Vec<_>>();
+            for rule in &problems {
+                println!(
+                    "{}: last triggered {}, {} failed invocations, {} failed tasks{}{}{}{}{}{}{}",
+                    rule.rule,
+                    rule.last_trigger
+                        .as_ref()
+                        .map(|ts| ago_rfc3339(ts))
+                        .unwrap_or_else(|| "never".into()),
+                    rule.failed_invocations,
+                    rule.stopped_tasks
+                        .iter()
+                        .filter(|task| task.failed())
+                        .count(),
+                    match rule.lambda_errors {
+                        Some(errors) if errors > 0 => format!(", {} lambda errors", errors),
+                        _ => String::new(),
+                    },
+                    if rule.stale { ", stale" } else { "" },
+                    if rule.overdue { ", overdue" } else { "" },
+                    if rule.triggered_without_task {
+                        ", triggered but started no task"
+                    } else {
+                        ""
+                    },
+                    if rule.broken_targets.is_empty() {
+                        String::new()
+                    } else {
+                        format!(", broken target {}", rule.broken_targets.join(", "))
+                    },
+                    match rule
+                        .stopped_tasks
+                        .iter()
+                        .filter(|task| task.out_of_memory)
+                        .count()
+                    {
+                        0 => String::new(),
+                        oom => format!(", {} out of memory", oom),
+                    },
+                    if rule.overlapping_tasks.is_empty() {
+                        String::new()
+                    } else {
+                        format!(
+                            ", {} overlapping tasks: {}",
+                            rule.overlapping_tasks.len(),
+                            rule.overlapping_tasks.join(", ")
+                        )
+                    }
+                );
+            }
+            for err in &report.errors {
+                println!("{}: failed to query: {}", err.rule, err.error);
+            }
+            println!("{}", report.summary);
+            if !problems.is_empty() {
+                std::process::exit(EXIT_UNHEALTHY);
+            }
+            if let Some(code) = incomplete(&report) {
+                std::process::exit(code);
+            }
+        }
+        Command::Serve { addr, cache } => {
+            let exporter = Arc::new(Exporter {
+                pipeline,
+                max_age,
+                cache: cache.map(|cache| cache.to_std().unwrap_or_default()),
+                cached: Mutex::new(None),
+                health: StdMutex::new(Health::default()),
+            });
+            let server = match Server::try_bind(&addr) {
+                Ok(server) => server,
+                Err(err) => fail(format!("failed to listen on {}: {}", addr, err), EXIT_USAGE),
+            };
+            eprintln!(
+                "serving metrics on http://{0}/metrics and health on http://{0}/health",
+                addr
+            );
+            let served = server
+                .serve(make_service_fn(move |_| {
+                    let exporter = exporter.clone();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |request| {
+                            exporter.clone().respond(request)
+                        }))
+                    }
+                }))
+                .with_graceful_shutdown(async {
+                    let _ = tokio::signal::ctrl_c().await;
                 })
-                .map_err(|e| e.to_string())
-                .map(|result| (rule, last, result.tasks.unwrap_or_default()))
+                .await;
+            if let Err(err) = served {
+                fail(err, EXIT_AWS_ERROR)
+            }
+        }
+        Command::Export { file } => {
+            let Outcome { results, errors } =
+                pipeline.run().await.unwrap_or_else(|err| run_failed(err));
+            let report = Report::new(results.iter().map(to_report).collect(), errors);
+            let json = serde_json::to_string_pretty(&report).expect("failed to serialize output");
+            write_output(file.as_deref(), &format!("{}\n", json));
+            if let Some(code) = incomplete(&report) {
+                std::process::exit(code);
+            }
+        }
+        Command::Completions { .. } => unreachable!("completions are generated before querying"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use cronitor::started_by;
+    use rusoto_cloudwatch::{
+        Datapoint, GetMetricStatisticsError, GetMetricStatisticsInput, GetMetricStatisticsOutput,
+        ListMetricsError, ListMetricsInput, ListMetricsOutput, PutMetricDataError,
+        PutMetricDataInput,
+    };
+    use rusoto_core::RusotoError;
+    use rusoto_ecs::{
+        Container, DescribeClustersError, DescribeClustersRequest, DescribeClustersResponse,
+        DescribeTaskDefinitionError, DescribeTaskDefinitionRequest, DescribeTaskDefinitionResponse,
+        DescribeTasksError, DescribeTasksRequest, DescribeTasksResponse, ListClustersError,
+        ListClustersRequest, ListClustersResponse, ListTasksError, ListTasksRequest,
+        ListTasksResponse, TaskDefinition,
+    };
+    use rusoto_events::{
+        DescribeRuleError, DescribeRuleRequest, DescribeRuleResponse, EcsParameters,
+        ListRulesError, ListRulesRequest, ListRulesResponse, ListTagsForResourceError,
+        ListTagsForResourceRequest, ListTagsForResourceResponse, ListTargetsByRuleError,
+        ListTargetsByRuleRequest, ListTargetsByRuleResponse, Rule as EventRule, Target,
+    };
+
+    const TASK_DEFINITION: &str = "arn:aws:ecs:us-east-1:123:task-definition/nightly:1";
+
+    /// rules and their targets
+    struct InMemoryEvents {
+        rules: Vec<(EventRule, Vec<Target>)>,
+    }
+
+    #[async_trait]
+    impl EventsApi for InMemoryEvents {
+        async fn list_rules(
+            &self,
+            input: ListRulesRequest,
+        ) -> Result<ListRulesResponse, RusotoError<ListRulesError>> {
+            let prefix = input.name_prefix.unwrap_or_default();
+            Ok(ListRulesResponse {
+                rules: Some(
+                    self.rules
+                        .iter()
+                        .map(|(rule, _)| rule.clone())
+                        .filter(|rule| {
+                            rule.name
+                                .as_deref()
+                                .unwrap_or_default()
+                                .starts_with(&prefix)
+                        })
+                        .collect(),
+                ),
+                next_token: None,
             })
-        }))
-    });
+        }
+
+        async fn describe_rule(
+            &self,
+            _: DescribeRuleRequest,
+        ) -> Result<DescribeRuleResponse, RusotoError<DescribeRuleError>> {
+            unimplemented!("rules are only listed by prefix")
+        }
+
+        async fn list_targets_by_rule(
+            &self,
+            input: ListTargetsByRuleRequest,
+        ) -> Result<ListTargetsByRuleResponse, RusotoError<ListTargetsByRuleError>> {
+            Ok(ListTargetsByRuleResponse {
+                targets: self
+                    .rules
+                    .iter()
+                    .find(|(rule, _)| rule.name.as_ref() == Some(&input.rule))
+                    .map(|(_, targets)| targets.clone()),
+                next_token: None,
+            })
+        }
+
+        async fn list_tags_for_resource(
+            &self,
+            _: ListTagsForResourceRequest,
+        ) -> Result<ListTagsForResourceResponse, RusotoError<ListTagsForResourceError>> {
+            unimplemented!("rules aren't filtered by tag")
+        }
+    }
+
+    /// datapoints by metric name and rule
+    struct InMemoryMetrics {
+        datapoints: HashMap<(&'static str, &'static str), Vec<Datapoint>>,
+    }
+
+    #[async_trait]
+    impl MetricsApi for InMemoryMetrics {
+        async fn get_metric_statistics(
+            &self,
+            input: GetMetricStatisticsInput,
+        ) -> Result<GetMetricStatisticsOutput, RusotoError<GetMetricStatisticsError>> {
+            let rule = input.dimensions.unwrap_or_default().remove(0).value;
+            let metric_name = input.metric_name;
+            Ok(GetMetricStatisticsOutput {
+                datapoints: self
+                    .datapoints
+                    .get(&(metric_name.as_str(), rule.as_str()))
+                    .cloned(),
+                label: Some(metric_name),
+            })
+        }
+
+        async fn list_metrics(
+            &self,
+            _: ListMetricsInput,
+        ) -> Result<ListMetricsOutput, RusotoError<ListMetricsError>> {
+            unimplemented!("access isn't validated")
+        }
+
+        async fn put_metric_data(
+            &self,
+            _: PutMetricDataInput,
+        ) -> Result<(), RusotoError<PutMetricDataError>> {
+            unimplemented!("metrics aren't published")
+        }
+    }
+
+    /// tasks of a single cluster
+    struct InMemoryEcs {
+        tasks: Vec<Task>,
+    }
+
+    #[async_trait]
+    impl EcsApi for InMemoryEcs {
+        async fn list_tasks(
+            &self,
+            input: ListTasksRequest,
+        ) -> Result<ListTasksResponse, RusotoError<ListTasksError>> {
+            Ok(ListTasksResponse {
+                task_arns: Some(
+                    self.tasks
+                        .iter()
+                        .filter(|task| {
+                            task.started_by == input.started_by
+                                && task.desired_status == input.desired_status
+                        })
+                        .filter_map(|task| task.task_arn.clone())
+                        .collect(),
+                ),
+                next_token: None,
+            })
+        }
+
+        async fn describe_tasks(
+            &self,
+            input: DescribeTasksRequest,
+        ) -> Result<DescribeTasksResponse, RusotoError<DescribeTasksError>> {
+            Ok(DescribeTasksResponse {
+                tasks: Some(
+                    self.tasks
+                        .iter()
+                        .filter(|task| {
+                            input
+                                .tasks
+                                .contains(task.task_arn.as_ref().expect("task arn"))
+                        })
+                        .cloned()
+                        .collect(),
+                ),
+                failures: None,
+            })
+        }
+
+        async fn describe_task_definition(
+            &self,
+            input: DescribeTaskDefinitionRequest,
+        ) -> Result<DescribeTaskDefinitionResponse, RusotoError<DescribeTaskDefinitionError>>
+        {
+            Ok(DescribeTaskDefinitionResponse {
+                task_definition: Some(TaskDefinition {
+                    task_definition_arn: Some(input.task_definition),
+                    status: Some("ACTIVE".into()),
+                    ..TaskDefinition::default()
+                }),
+                tags: None,
+            })
+        }
+
+        async fn describe_clusters(
+            &self,
+            _: DescribeClustersRequest,
+        ) -> Result<DescribeClustersResponse, RusotoError<DescribeClustersError>> {
+            unimplemented!("clusters aren't checked")
+        }
+
+        async fn list_clusters(
+            &self,
+            _: ListClustersRequest,
+        ) -> Result<ListClustersResponse, RusotoError<ListClustersError>> {
+            unimplemented!("clusters aren't checked")
+        }
+    }
+
+    fn pipeline<E: EventsApi, M: MetricsApi, C: EcsApi>(
+        events: E,
+        metrics: M,
+        ecs: C,
+    ) -> Pipeline<E, M, C> {
+        Pipeline {
+            events,
+            metrics,
+            ecs,
+            region: Region::UsEast1,
+            identity: Identity {
+                profile: None,
+                imds: false,
+                assume_role_arn: None,
+                external_id: None,
+                account: None,
+            },
+            bus: None,
+            prefixes: vec!["nightly".into()],
+            names: Vec::new(),
+            exact: false,
+            tags: Vec::new(),
+            rule_tags: StdMutex::new(HashMap::new()),
+            state: RuleState::Enabled,
+            query: MetricQuery {
+                since: Duration::days(1),
+                period: Duration::hours(1),
+                statistic: Statistic::Sum,
+                namespace: DEFAULT_NAMESPACE.into(),
+                dimension_name: DEFAULT_DIMENSION_NAME.into(),
+                end: None,
+            },
+            trend: false,
+            clusters: vec!["default".into()],
+            started_by: DEFAULT_STARTED_BY.into(),
+            status: Status::Stopped,
+            task_limit: None,
+            family: None,
+            reason_filter: None,
+            container: None,
+            concurrency: 2,
+            jitter: StdDuration::from_millis(0),
+            retries: Retries::throttled(0),
+            progress: false,
+            timeout: None,
+            rule_timeout: None,
+            cache: None,
+        }
+    }
+
+    fn rule(name: &str) -> (EventRule, Vec<Target>) {
+        (
+            EventRule {
+                name: Some(name.into()),
+                arn: Some(format!("arn:aws:events:us-east-1:123:rule/{}", name)),
+                state: Some("ENABLED".into()),
+                schedule_expression: Some("rate(1 hour)".into()),
+                ..EventRule::default()
+            },
+            vec![Target {
+                arn: "arn:aws:ecs:us-east-1:123:cluster/default".into(),
+                ecs_parameters: Some(EcsParameters {
+                    task_definition_arn: TASK_DEFINITION.into(),
+                    ..EcsParameters::default()
+                }),
+                ..Target::default()
+            }],
+        )
+    }
+
+    fn datapoint(
+        at: DateTime<Utc>,
+        sum: f64,
+    ) -> Datapoint {
+        Datapoint {
+            timestamp: Some(at.to_rfc3339()),
+            sum: Some(sum),
+            maximum: Some(sum),
+            ..Datapoint::default()
+        }
+    }
+
+    fn task(
+        rule: &str,
+        id: &str,
+        exit_code: i64,
+    ) -> Task {
+        Task {
+            task_arn: Some(format!("arn:aws:ecs:us-east-1:123:task/default/{}", id)),
+            task_definition_arn: Some(TASK_DEFINITION.into()),
+            started_by: Some(started_by(DEFAULT_STARTED_BY, rule)),
+            desired_status: Some("STOPPED".into()),
+            last_status: Some("STOPPED".into()),
+            containers: Some(vec![Container {
+                name: Some("app".into()),
+                exit_code: Some(exit_code),
+                ..Container::default()
+            }]),
+            ..Task::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn pipeline_reports_triggers_and_tasks_of_matching_rules() {
+        let now = Utc::now();
+        let last = now - Duration::hours(2);
+        let events = InMemoryEvents {
+            rules: vec![
+                rule("nightly-report"),
+                rule("nightly-cleanup"),
+                rule("nightly-sync"),
+                rule("hourly"),
+            ],
+        };
+        let metrics = InMemoryMetrics {
+            datapoints: vec![
+                (
+                    ("TriggeredRules", "nightly-report"),
+                    vec![
+                        datapoint(last, 1.0),
+                        datapoint(now - Duration::hours(3), 2.0),
+                    ],
+                ),
+                (
+                    ("FailedInvocations", "nightly-report"),
+                    vec![datapoint(last, 1.0)],
+                ),
+                (
+                    ("TriggeredRules", "nightly-sync"),
+                    vec![datapoint(now - Duration::minutes(10), 1.0)],
+                ),
+                (
+                    ("TriggeredRules", "hourly"),
+                    vec![datapoint(now - Duration::minutes(30), 1.0)],
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let ecs = InMemoryEcs {
+            tasks: vec![
+                task("nightly-report", "a", 0),
+                task("nightly-report", "b", 1),
+                task("hourly", "c", 0),
+            ],
+        };
+        let pipeline = pipeline(events, metrics, ecs);
+        let Outcome { results, errors } = pipeline.run().await.expect("pipeline failed");
+        let mut reports = results
+            .into_iter()
+            .map(|(rule, triggers, tasks)| {
+                RuleReport::new(
+                    rule,
+                    triggers,
+                    &tasks,
+                    pipeline.status,
+                    &pipeline.region,
+                    &pipeline.query,
+                    Some(Duration::days(1)),
+                    Timezone::Utc,
+                )
+            })
+            .collect::<Vec<_>>();
+        reports.sort_by(|a, b| a.rule.cmp(&b.rule));
+        let report = Report::new(reports, errors);
+
+        assert!(report.errors.is_empty());
+        let names = report
+            .rules
+            .iter()
+            .map(|rule| rule.rule.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["nightly-cleanup", "nightly-report", "nightly-sync"]
+        );
+
+        let cleanup = &report.rules[0];
+        assert_eq!(cleanup.trigger_count, 0);
+        assert_eq!(cleanup.last_trigger, None);
+        assert!(cleanup.stale);
+        assert!(cleanup.stopped_tasks.is_empty());
+
+        let nightly = &report.rules[1];
+        assert_eq!(nightly.target_type, TargetType::Ecs);
+        assert_eq!(nightly.task_definitions, vec![TASK_DEFINITION.to_string()]);
+        assert!(nightly.broken_targets.is_empty());
+        assert_eq!(nightly.trigger_count, 3);
+        assert_eq!(nightly.failed_invocations, 1);
+        assert_eq!(nightly.max_burst, Some(2));
+        assert_eq!(nightly.trigger_statistic, Some(3.0));
+        assert_eq!(
+            nightly.last_trigger,
+            Some(Timezone::Utc.format_rfc3339(&last.to_rfc3339()))
+        );
+        assert!(!nightly.stale);
+        assert_eq!(nightly.stopped_tasks.len(), 2);
+        assert_eq!(
+            nightly
+                .stopped_tasks
+                .iter()
+                .filter(|task| task.failed())
+                .count(),
+            1
+        );
+
+        assert!(!nightly.triggered_without_task);
+
+        // triggered within the hour ecs lists stopped tasks for, yet none were
+        let sync = &report.rules[2];
+        assert_eq!(sync.trigger_count, 1);
+        assert!(sync.stopped_tasks.is_empty());
+        assert!(sync.triggered_without_task);
+
+        assert_eq!(report.summary.rules, 3);
+        assert_eq!(report.summary.stale, 1);
+        assert_eq!(report.summary.with_stopped_tasks, 1);
+        assert_eq!(report.summary.with_failed_tasks, 1);
+        assert_eq!(report.summary.without_tasks, 1);
+    }
+
+    #[tokio::test]
+    async fn pipeline_doesnt_flag_recent_triggers_whose_task_is_still_running() {
+        let events = InMemoryEvents {
+            rules: vec![rule("nightly-sync")],
+        };
+        let metrics = InMemoryMetrics {
+            datapoints: vec![(
+                ("TriggeredRules", "nightly-sync"),
+                vec![datapoint(Utc::now() - Duration::minutes(10), 1.0)],
+            )]
+            .into_iter()
+            .collect(),
+        };
+        let running = Task {
+            desired_status: Some("RUNNING".into()),
+            last_status: Some("RUNNING".into()),
+            containers: None,
+            ..task("nightly-sync", "a", 0)
+        };
+        let ecs = InMemoryEcs {
+            tasks: vec![running],
+        };
+        let pipeline = pipeline(events, metrics, ecs);
+        let Outcome { results, errors } = pipeline.run().await.expect("pipeline failed");
+        assert!(errors.is_empty());
+        let (rule, triggers, tasks) = results.into_iter().next().expect("no results");
+        assert!(tasks.is_empty());
+        assert_eq!(rule.running_tasks.len(), 1);
+        let report = RuleReport::new(
+            rule,
+            triggers,
+            &tasks,
+            pipeline.status,
+            &pipeline.region,
+            &pipeline.query,
+            None,
+            Timezone::Utc,
+        );
+        assert!(!report.triggered_without_task);
+        assert!(!unhealthy(&report));
+    }
+
+    #[tokio::test]
+    async fn listing_filters_rendered_rules_but_not_the_summary() {
+        let events = InMemoryEvents {
+            rules: vec![rule("nightly-cleanup"), rule("nightly-report")],
+        };
+        let metrics = InMemoryMetrics {
+            datapoints: vec![(
+                ("TriggeredRules", "nightly-report"),
+                vec![datapoint(Utc::now() - Duration::hours(2), 1.0)],
+            )]
+            .into_iter()
+            .collect(),
+        };
+        let ecs = InMemoryEcs {
+            tasks: vec![task("nightly-report", "a", 0)],
+        };
+        let pipeline = pipeline(events, metrics, ecs);
+        let Outcome { results, errors } = pipeline.run().await.expect("pipeline failed");
+        let reports = results
+            .into_iter()
+            .map(|(rule, triggers, tasks)| {
+                RuleReport::new(
+                    rule,
+                    triggers,
+                    &tasks,
+                    pipeline.status,
+                    &pipeline.region,
+                    &pipeline.query,
+                    Some(Duration::days(1)),
+                    Timezone::Utc,
+                )
+            })
+            .collect();
+        let report = Report::new(reports, errors);
+        let listing = Listing {
+            only_stale: false,
+            quiet: false,
+            min_invocations: None,
+            max_invocations: None,
+        };
+        let names = |listing: Listing| {
+            listing
+                .shown(&report)
+                .rules
+                .into_iter()
+                .map(|rule| rule.rule)
+                .collect::<Vec<_>>()
+        };
+
+        // --quiet --fail-on-stale renders only the stale rule, yet still
+        // counts every rule checked
+        let quiet = Listing {
+            quiet: true,
+            ..listing
+        };
+        assert_eq!(names(quiet), vec!["nightly-cleanup"]);
+        let shown = quiet.shown(&report);
+        assert_eq!(shown.summary.rules, 2);
+        assert_eq!(shown.summary.stale, 1);
+        assert_eq!(nagios(&report).1, NAGIOS_CRITICAL);
+        assert!(nagios(&report).0.contains("rules=2 stale=1"));
+
+        // --min-invocations 1 --fail-on-stale hides the stale rule, which
+        // must still fail the run
+        let invoked = Listing {
+            min_invocations: Some(1),
+            ..listing
+        };
+        assert_eq!(names(invoked), vec!["nightly-report"]);
+        assert_eq!(invoked.shown(&report).summary.stale, 1);
+    }
 
-    println!("{:#?}", rt.block_on(stopped_tasks));
+    #[test]
+    fn parse_duration_rejects_overflowing_counts() {
+        assert_eq!(parse_duration("12h"), Ok(Duration::hours(12)));
+        assert_eq!(
+            parse_duration("9999999999999w"),
+            Err("invalid duration '9999999999999w': too long".to_string())
+        );
+        assert!(parse_duration("99999999999999999999s").is_err());
+    }
 }
@@ -0,0 +1,259 @@
+//! notifications sent when unhealthy rules are found
+
+use crate::{
+    api::MetricsApi, relative::ago_rfc3339, retry, CronitorError, Report, Retries, RuleReport,
+    DEFAULT_DIMENSION_NAME,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use hyper::{body, header::CONTENT_TYPE, Body, Client, Method, Request};
+use hyper_tls::HttpsConnector;
+use log::info;
+use rusoto_cloudwatch::{Dimension, MetricDatum, PutMetricDataInput};
+use rusoto_sns::{PublishInput, Sns};
+use serde_json::json;
+
+/// namespace cronitor publishes its own metrics to
+pub const METRIC_NAMESPACE: &str = "Cronitor";
+
+/// max number of datapoints accepted by a single put_metric_data request
+const PUT_METRIC_DATA_LIMIT: usize = 1000;
+
+/// a markdown summary of stale rules, or `None` when there are none worth
+/// notifying about
+pub fn stale_message(
+    clusters: &[String],
+    reports: &[RuleReport],
+) -> Option<String> {
+    let stale = reports
+        .iter()
+        .filter(|report| report.stale)
+        .collect::<Vec<_>>();
+    if stale.is_empty() {
+        return None;
+    }
+    let mut message = format!(
+        "*{} stale cron{} in {} `{}`*",
+        stale.len(),
+        if stale.len() == 1 { "" } else { "s" },
+        if clusters.len() == 1 {
+            "cluster"
+        } else {
+            "clusters"
+        },
+        clusters.join("`, `")
+    );
+    for report in stale {
+        message.push_str(&format!(
+            "\n• `{}` last triggered {}",
+            report.rule,
+            report
+                .last_trigger
+                .as_deref()
+                .map(ago_rfc3339)
+                .unwrap_or_else(|| "never".into())
+        ));
+    }
+    Some(message)
+}
+
+/// post stale rules to a slack incoming webhook, sending nothing when no
+/// rules are stale
+pub async fn slack(
+    webhook: &str,
+    clusters: &[String],
+    reports: &[RuleReport],
+) -> Result<(), CronitorError> {
+    let message = match stale_message(clusters, reports) {
+        Some(message) => message,
+        _ => return Ok(()),
+    };
+    post_json("slack", webhook, &json!({ "text": message })).await?;
+    info!("notified slack of stale rules in {}", clusters.join(", "));
+    Ok(())
+}
+
+/// post a json body to a service's url, failing on non-success responses
+async fn post_json(
+    service: &str,
+    url: &str,
+    value: &serde_json::Value,
+) -> Result<(), CronitorError> {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .map_err(|e| CronitorError::Http(format!("invalid {} url: {}", service, e)))?;
+    let response = Client::builder()
+        .build::<_, Body>(HttpsConnector::new())
+        .request(request)
+        .await
+        .map_err(|e| CronitorError::Http(e.to_string()))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = body::to_bytes(response.into_body())
+            .await
+            .map_err(|e| CronitorError::Http(e.to_string()))?;
+        return Err(CronitorError::Http(format!(
+            "{} responded with {}: {}",
+            service,
+            status,
+            String::from_utf8_lossy(&body)
+        )));
+    }
+    Ok(())
+}
+
+/// pagerduty events api v2 endpoint
+/// https://developer.pagerduty.com/docs/events-api-v2/trigger-events/
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+/// max length of a pagerduty event summary
+const MAX_PAGERDUTY_SUMMARY_LENGTH: usize = 1024;
+
+/// trigger a pagerduty event for each stale rule or rule with failed tasks,
+/// deduplicated by rule name so repeated runs update the same incident, and
+/// resolve the incidents of healthy rules when `resolve` is set
+pub async fn pagerduty(
+    routing_key: &str,
+    clusters: &[String],
+    reports: &[RuleReport],
+    resolve: bool,
+) -> Result<(), CronitorError> {
+    let mut triggered = 0;
+    for report in reports {
+        let dedup_key = format!("cronitor/{}", report.rule);
+        let failed = report
+            .stopped_tasks
+            .iter()
+            .filter(|task| task.failed())
+            .count();
+        let event = if report.stale || failed > 0 {
+            triggered += 1;
+            let summary = format!(
+                "cron {} in {} is {}",
+                report.rule,
+                clusters.join(", "),
+                match (report.stale, failed) {
+                    (true, 0) => "stale".to_string(),
+                    (true, failed) => format!("stale with {} failed tasks", failed),
+                    (_, failed) => format!("failing with {} failed tasks", failed),
+                }
+            )
+            .chars()
+            .take(MAX_PAGERDUTY_SUMMARY_LENGTH)
+            .collect::<String>();
+            json!({
+                "routing_key": routing_key,
+                "event_action": "trigger",
+                "dedup_key": dedup_key,
+                "payload": {
+                    "summary": summary,
+                    "source": "cronitor",
+                    "severity": "error",
+                    "custom_details": report,
+                },
+                "links": [{ "href": report.console_url, "text": "rule" }],
+            })
+        } else if resolve {
+            json!({
+                "routing_key": routing_key,
+                "event_action": "resolve",
+                "dedup_key": dedup_key,
+            })
+        } else {
+            continue;
+        };
+        post_json("pagerduty", PAGERDUTY_EVENTS_URL, &event).await?;
+    }
+    info!(
+        "triggered {} pagerduty events for rules in {}",
+        triggered,
+        clusters.join(", ")
+    );
+    Ok(())
+}
+
+/// max length of an sns message subject
+const MAX_SUBJECT_LENGTH: usize = 100;
+
+/// publish the json report to an sns topic when any rule is stale or has
+/// tasks which failed, or unconditionally when `always` is set
+pub async fn sns<S: Sns>(
+    sns: &S,
+    topic_arn: &str,
+    clusters: &[String],
+    report: &Report,
+    always: bool,
+) -> Result<(), CronitorError> {
+    let summary = &report.summary;
+    if !always && summary.stale == 0 && summary.with_failed_tasks == 0 {
+        return Ok(());
+    }
+    let subject = format!(
+        "cronitor: {} stale, {} failing crons in {}",
+        summary.stale,
+        summary.with_failed_tasks,
+        clusters.join(", ")
+    )
+    .chars()
+    .take(MAX_SUBJECT_LENGTH)
+    .collect();
+    sns.publish(PublishInput {
+        topic_arn: Some(topic_arn.into()),
+        subject: Some(subject),
+        message: serde_json::to_string(report).expect("failed to serialize report"),
+        ..PublishInput::default()
+    })
+    .await?;
+    info!(
+        "published report for {} to {}",
+        clusters.join(", "),
+        topic_arn
+    );
+    Ok(())
+}
+
+/// publish the seconds since each rule last triggered as a
+/// CronLastTriggerAgeSeconds metric, so cloudwatch alarms can fire on it
+///
+/// rules which haven't triggered within the window have no age to publish,
+/// so alarms should treat missing data as breaching
+pub async fn metrics<M: MetricsApi>(
+    metrics: &M,
+    reports: &[RuleReport],
+    retries: &Retries,
+) -> Result<(), CronitorError> {
+    let now = Utc::now();
+    let timestamp = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let data = reports
+        .iter()
+        .filter_map(|report| {
+            let last = DateTime::parse_from_rfc3339(report.last_trigger.as_deref()?).ok()?;
+            Some(MetricDatum {
+                metric_name: "CronLastTriggerAgeSeconds".into(),
+                dimensions: Some(vec![Dimension {
+                    name: DEFAULT_DIMENSION_NAME.into(),
+                    value: report.rule.clone(),
+                }]),
+                timestamp: Some(timestamp.clone()),
+                unit: Some("Seconds".into()),
+                value: Some(now.signed_duration_since(last).num_seconds().max(0) as f64),
+                ..MetricDatum::default()
+            })
+        })
+        .collect::<Vec<_>>();
+    for batch in data.chunks(PUT_METRIC_DATA_LIMIT) {
+        let input = PutMetricDataInput {
+            namespace: METRIC_NAMESPACE.into(),
+            metric_data: batch.to_vec(),
+        };
+        retry(retries, || metrics.put_metric_data(input.clone())).await?;
+    }
+    info!(
+        "published {} datapoints to {}",
+        data.len(),
+        METRIC_NAMESPACE
+    );
+    Ok(())
+}
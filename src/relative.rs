@@ -0,0 +1,49 @@
+//! human friendly formatting of times relative to now, e.g. "3h ago"
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+
+/// format a duration in its largest whole unit, e.g. "3h", or `None` when
+/// it's under a minute
+pub fn span(duration: Duration) -> Option<String> {
+    if duration.num_weeks() > 0 {
+        Some(format!("{}w", duration.num_weeks()))
+    } else if duration.num_days() > 0 {
+        Some(format!("{}d", duration.num_days()))
+    } else if duration.num_hours() > 0 {
+        Some(format!("{}h", duration.num_hours()))
+    } else if duration.num_minutes() > 0 {
+        Some(format!("{}m", duration.num_minutes()))
+    } else {
+        None
+    }
+}
+
+/// format a past time relative to now, e.g. "3h ago" or "just now"
+pub fn ago<T: TimeZone>(time: &DateTime<T>) -> String {
+    span(Utc::now().signed_duration_since(time.clone()))
+        .map(|span| format!("{} ago", span))
+        .unwrap_or_else(|| "just now".into())
+}
+
+/// format a future time relative to now, e.g. "in 3h" or "now"
+pub fn until<T: TimeZone>(time: &DateTime<T>) -> String {
+    span(time.clone().signed_duration_since(Utc::now()))
+        .map(|span| format!("in {}", span))
+        .unwrap_or_else(|| "now".into())
+}
+
+/// format a past RFC3339 timestamp relative to now, passing through values
+/// we can't parse untouched
+pub fn ago_rfc3339(timestamp: &str) -> String {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|time| ago(&time))
+        .unwrap_or_else(|_| timestamp.into())
+}
+
+/// format a future RFC3339 timestamp relative to now, passing through
+/// values we can't parse untouched
+pub fn until_rfc3339(timestamp: &str) -> String {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|time| until(&time))
+        .unwrap_or_else(|_| timestamp.into())
+}
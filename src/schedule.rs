@@ -0,0 +1,265 @@
+//! parsing of Cloud Watch event rule schedule expressions
+//!
+//! https://docs.aws.amazon.com/eventbridge/latest/userguide/eb-create-rule-schedule.html
+
+use chrono::{prelude::*, Duration};
+use std::str::FromStr;
+
+/// how far ahead to search for a cron expression's next fire time
+const MAX_SEARCH_DAYS: i64 = 366 * 5;
+
+/// a parsed `rate(...)` or `cron(...)` schedule expression
+#[derive(Clone, Debug)]
+pub enum Schedule {
+    Rate(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    /// the first time at or after `from` that this schedule fires
+    ///
+    /// rate schedules are anchored to when their rule was created, which we
+    /// don't know, so they're anchored to `from` instead
+    pub fn next_from(
+        &self,
+        from: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Rate(rate) => Some(from + *rate),
+            Schedule::Cron(cron) => cron.next_from(from),
+        }
+    }
+
+    /// the next time after `now` that this schedule fires, anchoring rate
+    /// schedules to their last trigger
+    pub fn next_run(
+        &self,
+        last_trigger: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Rate(rate) => {
+                let last = last_trigger?;
+                let elapsed = now.signed_duration_since(last).num_seconds().max(0);
+                Some(last + *rate * (elapsed / rate.num_seconds() + 1) as i32)
+            }
+            Schedule::Cron(cron) => cron.next_from(now),
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if let Some(rate) = value
+            .strip_prefix("rate(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            parse_rate(rate).map(Schedule::Rate)
+        } else if let Some(cron) = value
+            .strip_prefix("cron(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            cron.parse().map(Schedule::Cron)
+        } else {
+            Err(format!(
+                "invalid schedule '{}': expected rate(...) or cron(...)",
+                value
+            ))
+        }
+    }
+}
+
+/// parse the body of a rate expression, e.g. "5 minutes"
+fn parse_rate(value: &str) -> Result<Duration, String> {
+    let mut parts = value.split_whitespace();
+    let (count, unit) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(count), Some(unit), None) => (count, unit),
+        _ => {
+            return Err(format!(
+                "invalid rate '{}': expected a value and unit",
+                value
+            ))
+        }
+    };
+    let count = count
+        .parse::<i64>()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or_else(|| format!("invalid rate '{}': value must be a positive integer", value))?;
+    match unit {
+        "minute" | "minutes" => Ok(Duration::minutes(count)),
+        "hour" | "hours" => Ok(Duration::hours(count)),
+        "day" | "days" => Ok(Duration::days(count)),
+        _ => Err(format!(
+            "invalid rate '{}': unit must be one of minutes, hours, or days",
+            value
+        )),
+    }
+}
+
+/// the values allowed for a single cron field, where `None` matches any value
+#[derive(Clone, Debug)]
+struct Field(Option<Vec<u32>>);
+
+impl Field {
+    fn matches(
+        &self,
+        value: u32,
+    ) -> bool {
+        self.0
+            .as_ref()
+            .map(|values| values.contains(&value))
+            .unwrap_or(true)
+    }
+
+    /// parse a comma separated list of values, ranges, and steps, e.g. "0-10/2,30"
+    fn parse(
+        value: &str,
+        min: u32,
+        max: u32,
+        names: &[&str],
+    ) -> Result<Self, String> {
+        if value == "*" || value == "?" {
+            return Ok(Field(None));
+        }
+        let number = |value: &str| -> Result<u32, String> {
+            let parsed = names
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(value))
+                .map(|position| min + position as u32)
+                .map(Ok)
+                .unwrap_or_else(|| value.parse::<u32>())
+                .map_err(|_| format!("unsupported cron value '{}'", value))?;
+            if parsed < min || parsed > max {
+                return Err(format!(
+                    "cron value '{}' must be between {} and {}",
+                    value, min, max
+                ));
+            }
+            Ok(parsed)
+        };
+        let mut values = Vec::new();
+        for part in value.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (
+                    range,
+                    step.parse::<u32>()
+                        .ok()
+                        .filter(|step| *step > 0)
+                        .ok_or_else(|| format!("invalid cron step '{}'", step))?,
+                ),
+                _ => (part, 1),
+            };
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                _ if range == "*" => (min, max),
+                // a bare start with a step, e.g. 5/15, runs through the max
+                _ if step > 1 => (number(range)?, max),
+                _ => {
+                    let value = number(range)?;
+                    (value, value)
+                }
+            };
+            values.extend((start..=end).step_by(step as usize));
+        }
+        Ok(Field(Some(values)))
+    }
+}
+
+/// a six field aws cron expression: minutes, hours, day of month, month,
+/// day of week, and year, always evaluated in UTC
+#[derive(Clone, Debug)]
+pub struct Cron {
+    minutes: Field,
+    hours: Field,
+    days_of_month: Field,
+    months: Field,
+    days_of_week: Field,
+    years: Field,
+}
+
+impl Cron {
+    /// the first time at or after `from` that this expression matches
+    pub fn next_from(
+        &self,
+        from: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let start = from.with_second(0)?.with_nanosecond(0)?;
+        let start = if start < from {
+            start + Duration::minutes(1)
+        } else {
+            start
+        };
+        (0..MAX_SEARCH_DAYS)
+            .map(|offset| start.date_naive() + Duration::days(offset))
+            .filter(|day| self.matches_day(*day))
+            .find_map(|day| {
+                let earliest = if day == start.date_naive() {
+                    (start.hour(), start.minute())
+                } else {
+                    (0, 0)
+                };
+                (0..24)
+                    .filter(|hour| self.hours.matches(*hour))
+                    .flat_map(|hour| {
+                        (0..60)
+                            .filter(|minute| self.minutes.matches(*minute))
+                            .map(move |minute| (hour, minute))
+                    })
+                    .find(|time| *time >= earliest)
+                    .and_then(|(hour, minute)| day.and_hms_opt(hour, minute, 0))
+                    .map(|time| Utc.from_utc_datetime(&time))
+            })
+    }
+
+    fn matches_day(
+        &self,
+        day: NaiveDate,
+    ) -> bool {
+        self.years.matches(day.year() as u32)
+            && self.months.matches(day.month())
+            && self.days_of_month.matches(day.day())
+            // aws numbers days of the week from 1 for sunday
+            && self.days_of_week.matches(day.weekday().number_from_sunday())
+    }
+}
+
+impl FromStr for Cron {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let fields = value.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 6 {
+            return Err(format!(
+                "invalid cron '{}': expected 6 fields but found {}",
+                value,
+                fields.len()
+            ));
+        }
+        let invalid = |err: String| format!("invalid cron '{}': {}", value, err);
+        Ok(Cron {
+            minutes: Field::parse(fields[0], 0, 59, &[]).map_err(invalid)?,
+            hours: Field::parse(fields[1], 0, 23, &[]).map_err(invalid)?,
+            days_of_month: Field::parse(fields[2], 1, 31, &[]).map_err(invalid)?,
+            months: Field::parse(
+                fields[3],
+                1,
+                12,
+                &[
+                    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV",
+                    "DEC",
+                ],
+            )
+            .map_err(invalid)?,
+            days_of_week: Field::parse(
+                fields[4],
+                1,
+                7,
+                &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"],
+            )
+            .map_err(invalid)?,
+            years: Field::parse(fields[5], 1970, 2199, &[]).map_err(invalid)?,
+        })
+    }
+}